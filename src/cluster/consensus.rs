@@ -0,0 +1,154 @@
+//! This module contains consensus clustering: elements are clustered at several thresholds and
+//! pairs are scored by how often they end up together, which is far more robust than trusting a
+//! single cut.
+
+use std::collections::HashMap;
+
+use crate::{Index, Size};
+use crate::index_pair::IndexPair;
+use crate::sim_matrix::SimilarityMatrix;
+use crate::sim_metric::Similarity;
+
+use super::{Cluster, Clusterer};
+
+/// The co-association of a set of elements: for each pair, the number of clusterings in which
+/// both shared a cluster. Pairs never co-clustered aren't stored.
+#[derive(Clone, Debug)]
+pub struct CoAssociation {
+    /// The elements clustered, as indices into the similarity matrix.
+    indices: Vec<Index>,
+    /// The number of clusterings performed.
+    clustering_count: Size,
+    /// The number of clusterings co-clustering each pair, keyed by ascending index pair.
+    pair_counts: HashMap<IndexPair, Size>,
+}
+
+impl CoAssociation {
+    /// Return the fraction of clusterings in which two elements shared a cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The first element's index in the similarity matrix.
+    /// * `j` - The second element's index in the similarity matrix.
+    ///
+    /// # Return
+    ///
+    /// The co-association score between `0.0` (never together) and `1.0` (always together).
+    pub fn score(&self, i: Index, j: Index) -> f64 {
+        if self.clustering_count == 0 {
+            return 0.0;
+        }
+
+        let count = self.pair_counts.get(&(i.min(j), i.max(j))).copied().unwrap_or(0);
+        count as f64 / self.clustering_count as f64
+    }
+
+    /// Return every co-clustered pair with its score, in ascending pair order.
+    pub fn scores(&self) -> Vec<(Index, Index, f64)> {
+        let mut scores =
+            self.pair_counts.keys()
+                .map(|&(i, j)| (i, j, self.score(i, j)))
+                .collect::<Vec<(Index, Index, f64)>>();
+        scores.sort_by_key(|&(i, j, _)| (i, j));
+        scores
+    }
+
+    /// Extract consensus clusters: the connected components of the pairs co-clustered at least
+    /// `min_score` of the time. Elements with no such pair become singletons.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_score` - The minimum co-association score linking two elements.
+    ///
+    /// # Return
+    ///
+    /// The consensus clusters as indices into the similarity matrix, ordered by their smallest
+    /// member, each with ascending members.
+    pub fn consensus_clusters(&self, min_score: f64) -> Vec<Cluster> {
+        let mut sorted_indices = self.indices.clone();
+        sorted_indices.sort_unstable();
+        let positions =
+            sorted_indices.iter().enumerate()
+                .map(|(position, index)| (*index, position))
+                .collect::<HashMap<Index, usize>>();
+
+        let triplets =
+            self.scores().into_iter()
+                .map(|(i, j, score)| (positions[&i], positions[&j], score))
+                .collect::<Vec<(Index, Index, Similarity)>>();
+        let score_matrix = SimilarityMatrix::from_triplets(sorted_indices.len(), 0.0, triplets);
+
+        Clusterer::single_linkage(&score_matrix).cut_at(min_score).into_iter()
+            .map(|cluster| cluster.into_iter().map(|position| sorted_indices[position]).collect())
+            .collect::<Vec<Cluster>>()
+    }
+}
+
+/// Consensus clustering implementation.
+impl Clusterer {
+    /// Cluster a set of elements at each of a set of thresholds, recording how often each pair
+    /// of elements shares a cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `indices` - The elements to cluster. Must not be empty.
+    /// * `thresholds` - The minimum similarities to cluster at.
+    ///
+    /// # Return
+    ///
+    /// The elements' co-association.
+    pub fn co_association(
+        similarity_matrix: &SimilarityMatrix,
+        indices: &[Index],
+        thresholds: &[Similarity],
+    ) -> CoAssociation {
+        let mut pair_counts: HashMap<IndexPair, Size> = HashMap::new();
+
+        for &threshold in thresholds {
+            let clusters = Clusterer::cluster_drop_matrix(similarity_matrix.spin_off(indices, threshold));
+            for cluster in clusters {
+                let mut members = cluster.iter().map(|position| indices[*position]).collect::<Vec<Index>>();
+                members.sort_unstable();
+                for (position, i) in members.iter().enumerate() {
+                    for j in &members[position + 1..] {
+                        *pair_counts.entry((*i, *j)).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        CoAssociation { indices: indices.to_vec(), clustering_count: thresholds.len(), pair_counts }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::matrix_from;
+
+    #[test]
+    fn scores_co_association() {
+        // 0-1 stay together at all thresholds but the highest, 1-2 only at the lowest, 3-4 never meet.
+        let similarity_matrix = matrix_from(5, &[(0, 1, 0.9), (1, 2, 0.6), (2, 3, 0.1)]);
+
+        let co_association =
+            Clusterer::co_association(&similarity_matrix, &[0, 1, 2, 3, 4], &[0.5, 0.7, 0.8, 0.95]);
+
+        assert_eq!(co_association.score(1, 0), 0.75);
+        assert_eq!(co_association.score(1, 2), 0.25);
+        assert_eq!(co_association.score(3, 4), 0.0);
+        assert_eq!(co_association.scores(), vec![(0, 1, 0.75), (0, 2, 0.25), (1, 2, 0.25)]);
+    }
+
+    #[test]
+    fn extracts_consensus_clusters() {
+        let similarity_matrix = matrix_from(6, &[(0, 1, 0.9), (1, 2, 0.6), (3, 4, 0.8), (4, 5, 0.55)]);
+
+        let co_association =
+            Clusterer::co_association(&similarity_matrix, &[5, 4, 3, 2, 1, 0], &[0.5, 0.7, 0.85]);
+
+        assert_eq!(co_association.consensus_clusters(0.5), vec![vec![0, 1], vec![2], vec![3, 4], vec![5]]);
+        assert_eq!(co_association.consensus_clusters(0.3), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+    }
+}
@@ -0,0 +1,112 @@
+//! This module contains a DBSCAN-style density clustering over a similarity matrix, provided as a
+//! well-understood baseline against which to compare grappolo's own algorithm.
+
+use std::collections::VecDeque;
+
+use crate::{Index, Size};
+use crate::sim_matrix::SimilarityMatrix;
+use crate::sim_metric::Similarity;
+
+use super::{Cluster, Clusterer, ClusteringResult};
+
+/// DBSCAN implementation.
+impl Clusterer {
+    /// Cluster a similarity matrix by density reachability.
+    ///
+    /// An element is a *core* element if it has at least `min_pts` siblings whose similarity is
+    /// at least `eps_similarity`. Clusters grow from core elements to every element reachable
+    /// through a chain of core elements. Elements not reachable from any core element are
+    /// reported as noise.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `eps_similarity` - The minimum similarity for two elements to be neighbors.
+    /// * `min_pts` - The minimum neighbor count for an element to be a core element.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result, with non-clustered elements in its `noise` list.
+    pub fn dbscan(
+        similarity_matrix: SimilarityMatrix,
+        eps_similarity: Similarity,
+        min_pts: Size,
+    ) -> ClusteringResult {
+        let neighbors =
+            similarity_matrix.rows.iter()
+                .map(|row| row.cut_at(eps_similarity))
+                .collect::<Vec<Vec<Index>>>();
+        let is_core = |index: Index| neighbors[index].len() >= min_pts;
+
+        let mut assigned = vec![false; similarity_matrix.size()];
+        let mut clusters: Vec<Cluster> = Vec::new();
+
+        for index in 0..similarity_matrix.size() {
+            if assigned[index] || !is_core(index) {
+                continue;
+            }
+
+            let mut cluster = vec![index];
+            assigned[index] = true;
+
+            let mut pending = VecDeque::from(vec![index]);
+            while let Some(current_index) = pending.pop_front() {
+                for &neighbor in &neighbors[current_index] {
+                    if !assigned[neighbor] {
+                        assigned[neighbor] = true;
+                        cluster.push(neighbor);
+                        if is_core(neighbor) {
+                            pending.push_back(neighbor);
+                        }
+                    }
+                }
+            }
+
+            clusters.push(cluster);
+        }
+
+        let noise =
+            (0..similarity_matrix.size())
+                .filter(|index| !assigned[*index])
+                .collect::<Vec<Index>>();
+
+        let result = ClusteringResult::new(clusters, noise, similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::matrix_from;
+
+    #[test]
+    fn clusters_by_density() {
+        // 0-1-2-3 is a dense chain, 4 hangs off 3 weakly, 5 and 6 are a lone pair.
+        let edges = vec![
+            (0, 1, 0.9), (0, 2, 0.8), (1, 2, 0.9), (1, 3, 0.8), (2, 3, 0.9),
+            (3, 4, 0.8),
+            (5, 6, 0.9),
+        ];
+
+        let clustering = Clusterer::dbscan(matrix_from(7, &edges), 0.8, 2);
+
+        let mut clusters = clustering.clusters;
+        for cluster in clusters.iter_mut() {
+            cluster.sort();
+        }
+        assert_eq!(clusters, vec![vec![0, 1, 2, 3, 4]]);
+        assert_eq!(clustering.noise, vec![5, 6]);
+    }
+
+    #[test]
+    fn marks_everything_as_noise_without_core_elements() {
+        let edges = vec![(0, 1, 0.9), (2, 3, 0.9)];
+
+        let clustering = Clusterer::dbscan(matrix_from(4, &edges), 0.95, 1);
+
+        assert!(clustering.clusters.is_empty());
+        assert_eq!(clustering.noise, vec![0, 1, 2, 3]);
+    }
+}
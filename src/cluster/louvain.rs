@@ -0,0 +1,225 @@
+//! This module contains a Louvain modularity-optimization clustering over a similarity matrix,
+//! provided as a principled, tunable baseline against which to compare grappolo's own algorithm.
+
+use std::collections::HashMap;
+
+use crate::Index;
+use crate::sim_matrix::SimilarityMatrix;
+use crate::sim_metric::Similarity;
+
+use super::{Cluster, Clusterer, ClusteringResult};
+
+/// The smallest modularity gain considered an improvement. Guards against endless moves caused by
+/// floating point noise.
+const MIN_GAIN: Similarity = 1e-12;
+
+/// A weighted graph whose nodes are communities of the level below.
+struct Level {
+    /// Each node's neighbors and edge weights, excluding the node itself.
+    neighbors: Vec<Vec<(Index, Similarity)>>,
+    /// Each node's self-loop weight, i.e. the weight internal to the community it stands for.
+    loops: Vec<Similarity>,
+}
+
+impl Level {
+    /// Return each node's degree, counting its self-loop twice.
+    fn degrees(&self) -> Vec<Similarity> {
+        self.neighbors.iter().zip(self.loops.iter())
+            .map(|(neighbors, self_loop)| {
+                neighbors.iter().map(|(_, weight)| weight).sum::<Similarity>() + 2.0 * self_loop
+            })
+            .collect()
+    }
+
+    /// Move nodes greedily between neighboring communities while modularity improves.
+    ///
+    /// # Return
+    ///
+    /// Each node's community, numbered densely from `0`, and whether any node moved.
+    fn move_nodes(&self, resolution: f64) -> (Vec<Index>, bool) {
+        let degrees = self.degrees();
+        let total_weight = degrees.iter().sum::<Similarity>();
+
+        let mut communities = (0..self.neighbors.len()).collect::<Vec<Index>>();
+        let mut community_degrees = degrees.clone();
+        let mut moved = false;
+
+        if total_weight > 0.0 {
+            let mut improved = true;
+            while improved {
+                improved = false;
+
+                for node in 0..self.neighbors.len() {
+                    let current = communities[node];
+                    community_degrees[current] -= degrees[node];
+
+                    let mut weights_to: HashMap<Index, Similarity> = HashMap::new();
+                    weights_to.insert(current, 0.0);
+                    for &(neighbor, weight) in &self.neighbors[node] {
+                        *weights_to.entry(communities[neighbor]).or_insert(0.0) += weight;
+                    }
+
+                    let gain = |community: Index| {
+                        weights_to[&community] -
+                            resolution * community_degrees[community] * degrees[node] / total_weight
+                    };
+
+                    let mut best = current;
+                    let mut best_gain = gain(current);
+                    let mut candidates = weights_to.keys().copied().collect::<Vec<Index>>();
+                    candidates.sort_unstable();
+                    for community in candidates {
+                        let community_gain = gain(community);
+                        if community_gain > best_gain + MIN_GAIN {
+                            best = community;
+                            best_gain = community_gain;
+                        }
+                    }
+
+                    community_degrees[best] += degrees[node];
+                    if best != current {
+                        communities[node] = best;
+                        improved = true;
+                        moved = true;
+                    }
+                }
+            }
+        }
+
+        let mut renumbering = HashMap::new();
+        for community in communities.iter_mut() {
+            let next = renumbering.len();
+            *community = *renumbering.entry(*community).or_insert(next);
+        }
+
+        (communities, moved)
+    }
+
+    /// Collapse each community into a single node of a new level.
+    fn aggregate(&self, communities: &[Index], community_count: usize) -> Level {
+        let mut loops = vec![0.0; community_count];
+        let mut weights = vec![HashMap::new(); community_count];
+
+        for (node, neighbors) in self.neighbors.iter().enumerate() {
+            let community = communities[node];
+            loops[community] += self.loops[node];
+            for &(neighbor, weight) in neighbors {
+                let neighbor_community = communities[neighbor];
+                if neighbor_community == community {
+                    // Each internal edge is listed from both ends.
+                    loops[community] += weight / 2.0;
+                } else {
+                    *weights[community].entry(neighbor_community).or_insert(0.0) += weight;
+                }
+            }
+        }
+
+        let neighbors =
+            weights.into_iter()
+                .map(|weights| {
+                    let mut neighbors = weights.into_iter().collect::<Vec<(Index, Similarity)>>();
+                    neighbors.sort_unstable_by_key(|(neighbor, _)| *neighbor);
+                    neighbors
+                })
+                .collect();
+
+        Level { neighbors, loops }
+    }
+}
+
+/// Louvain implementation.
+impl Clusterer {
+    /// Cluster a similarity matrix by greedily maximizing modularity, the Louvain method.
+    ///
+    /// Each element starts in its own community and is moved to the neighboring community that
+    /// most increases modularity. Once no move helps, communities are collapsed into single nodes
+    /// and the process repeats until no node moves at all.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `resolution` - The modularity resolution. Values above `1.0` yield more, smaller clusters;
+    ///   values below `1.0` yield fewer, larger ones.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result.
+    pub fn louvain(similarity_matrix: SimilarityMatrix, resolution: f64) -> ClusteringResult {
+        let mut level = Level {
+            neighbors:
+            similarity_matrix.rows.iter()
+                .map(|row| {
+                    row.scores.iter()
+                        .map(|score| (score.sibling_index, score.similarity))
+                        .collect()
+                })
+                .collect(),
+            loops: vec![0.0; similarity_matrix.size()],
+        };
+        let mut memberships = (0..similarity_matrix.size()).collect::<Vec<Index>>();
+
+        loop {
+            let (communities, moved) = level.move_nodes(resolution);
+            if !moved {
+                break;
+            }
+
+            let community_count = communities.iter().max().map_or(0, |max| max + 1);
+            for membership in memberships.iter_mut() {
+                *membership = communities[*membership];
+            }
+            level = level.aggregate(&communities, community_count);
+        }
+
+        let mut clusters: Vec<Cluster> = vec![Vec::new(); level.neighbors.len()];
+        for (index, membership) in memberships.into_iter().enumerate() {
+            clusters[membership].push(index);
+        }
+        clusters.retain(|cluster| !cluster.is_empty());
+        clusters.sort_by_key(|cluster| cluster[0]);
+
+        let result = ClusteringResult::new(clusters, vec![], similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::matrix_from;
+
+    fn two_triangles() -> SimilarityMatrix {
+        // 0-1-2 and 3-4-5 are tight triangles joined by a weak 2-3 edge; 6 is isolated.
+        let edges = vec![
+            (0, 1, 0.9), (0, 2, 0.8), (1, 2, 0.9),
+            (3, 4, 0.9), (3, 5, 0.8), (4, 5, 0.9),
+            (2, 3, 0.1),
+        ];
+        matrix_from(7, &edges)
+    }
+
+    #[test]
+    fn clusters_by_modularity() {
+        let clustering = Clusterer::louvain(two_triangles(), 1.0);
+
+        assert_eq!(clustering.clusters, vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]]);
+        assert!(clustering.noise.is_empty());
+    }
+
+    #[test]
+    fn tunes_granularity_by_resolution() {
+        let coarse = Clusterer::louvain(two_triangles(), 0.0);
+        assert_eq!(coarse.clusters, vec![vec![0, 1, 2, 3, 4, 5], vec![6]]);
+
+        let fine = Clusterer::louvain(two_triangles(), 100.0);
+        assert_eq!(fine.clusters.len(), 7);
+    }
+
+    #[test]
+    fn keeps_edgeless_elements_apart() {
+        let clustering = Clusterer::louvain(matrix_from(3, &[]), 1.0);
+
+        assert_eq!(clustering.clusters, vec![vec![0], vec![1], vec![2]]);
+    }
+}
@@ -0,0 +1,2395 @@
+//! This module contains the implementation of grappolo's clustering algorithm.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::Deref;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::{Index, Size};
+use crate::evaluation::ClusterEvaluator;
+use crate::graph::Graph;
+use crate::index_pair::IndexPair;
+use crate::sim_matrix::{RankMode, Row, SimilarityMatrix};
+use crate::sim_metric::Similarity;
+
+pub mod consensus;
+pub mod dbscan;
+pub mod louvain;
+pub mod single_linkage;
+pub mod sweep;
+
+/// A cluster is a vector of indices into the input set.
+pub type Cluster = Vec<Index>;
+
+/// A bare collection of clusters, collected from any iterator of clusters. It dereferences to a
+/// slice of clusters.
+///
+/// ```
+/// use grappolo::cluster::{Clusterer, Clustering};
+/// use grappolo::sim_matrix::SimilarityMatrix;
+///
+/// let clustering = Clusterer::cluster(SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.9)]));
+///
+/// let non_singletons = clustering.into_iter().filter(|cluster| cluster.len() > 1).collect::<Clustering>();
+/// assert_eq!(non_singletons.len(), 1);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Clustering(pub Vec<Cluster>);
+
+impl Clustering {
+    /// Unwrap the clusters.
+    pub fn into_inner(self) -> Vec<Cluster> {
+        self.0
+    }
+}
+
+impl Deref for Clustering {
+    type Target = [Cluster];
+
+    fn deref(&self) -> &[Cluster] {
+        &self.0
+    }
+}
+
+impl FromIterator<Cluster> for Clustering {
+    fn from_iter<I: IntoIterator<Item=Cluster>>(iter: I) -> Clustering {
+        Clustering(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Clustering {
+    type Item = Cluster;
+    type IntoIter = std::vec::IntoIter<Cluster>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Clustering {
+    type Item = &'a Cluster;
+    type IntoIter = std::slice::Iter<'a, Cluster>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// A cluster formatted with its members' elements, by default as `count,element1,element2`.
+///
+/// ```
+/// use grappolo::cluster::ClusterDisplay;
+///
+/// let names = vec!["alejandro", "alexandro", "marlene"];
+///
+/// assert_eq!(ClusterDisplay::new(&[1, 0], &names).to_string(), "2,alexandro,alejandro");
+/// assert_eq!(ClusterDisplay::new(&[2], &names).separator("\t").with_count(false).to_string(), "marlene");
+/// ```
+pub struct ClusterDisplay<'a, T> {
+    /// The cluster's member indices.
+    cluster: &'a [Index],
+    /// The input set the indices refer to.
+    elements: &'a [T],
+    /// The text written between fields.
+    separator: &'a str,
+    /// Whether the member count is written as the first field.
+    with_count: bool,
+}
+
+impl<'a, T> ClusterDisplay<'a, T> {
+    /// Create a new cluster display writing the count followed by each member, comma-separated.
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster` - The cluster's member indices.
+    /// * `elements` - The input set the indices refer to.
+    pub fn new(cluster: &'a [Index], elements: &'a [T]) -> ClusterDisplay<'a, T> {
+        ClusterDisplay { cluster, elements, separator: ",", with_count: true }
+    }
+
+    /// Set the text written between fields.
+    pub fn separator(mut self, separator: &'a str) -> ClusterDisplay<'a, T> {
+        self.separator = separator;
+        self
+    }
+
+    /// Set whether the member count is written as the first field.
+    pub fn with_count(mut self, with_count: bool) -> ClusterDisplay<'a, T> {
+        self.with_count = with_count;
+        self
+    }
+}
+
+impl<'a, T: fmt::Display> fmt::Display for ClusterDisplay<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut separator = "";
+        if self.with_count {
+            write!(f, "{}", self.cluster.len())?;
+            separator = self.separator;
+        }
+        for index in self.cluster {
+            write!(f, "{}{}", separator, self.elements[*index])?;
+            separator = self.separator;
+        }
+        Ok(())
+    }
+}
+
+/// Violations of the partition invariant: every input index appears exactly once across all
+/// clusters (and noise).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClusterError {
+    /// An index appears more than once.
+    Duplicate { index: Index },
+    /// An index appears nowhere.
+    Missing { index: Index },
+    /// An index is not less than the input set size.
+    OutOfRange { index: Index, size: Size },
+}
+
+impl fmt::Display for ClusterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClusterError::Duplicate { index } =>
+                write!(f, "Index {} appears in more than one cluster", index),
+            ClusterError::Missing { index } =>
+                write!(f, "Index {} appears in no cluster", index),
+            ClusterError::OutOfRange { index, size } =>
+                write!(f, "Index {} is out of range for size {}", index, size),
+        }
+    }
+}
+
+impl Error for ClusterError {}
+
+/// Result of a clustering run, comprised of one or more `Cluster`s.
+pub struct ClusteringResult {
+    pub clusters: Vec<Cluster>,
+    /// Elements left out of all clusters. Only density-based clustering produces noise;
+    /// grappolo's own algorithm places every element in a cluster.
+    pub noise: Vec<Index>,
+    pub similarity_matrix: SimilarityMatrix,
+    labels: OnceLock<Vec<Option<usize>>>,
+}
+
+impl ClusteringResult {
+    /// Create a new clustering result.
+    ///
+    /// # Arguments
+    ///
+    /// * `clusters` - The clusters found.
+    /// * `noise` - The elements left out of all clusters.
+    /// * `similarity_matrix` - The similarity matrix the clusters were built from.
+    ///
+    /// # Return
+    ///
+    /// The new clustering result.
+    pub fn new(clusters: Vec<Cluster>, noise: Vec<Index>, similarity_matrix: SimilarityMatrix) -> Self {
+        ClusteringResult { clusters, noise, similarity_matrix, labels: OnceLock::new() }
+    }
+
+    /// Label each element with the position of the cluster containing it, or `None` for noise.
+    /// Labels are built on first call and cached: callers mutating `clusters` or `noise`
+    /// afterwards must call `reset_labels` for them to be rebuilt.
+    ///
+    /// # Return
+    ///
+    /// A slice holding each element's cluster position, indexed by element.
+    pub fn labels(&self) -> &[Option<usize>] {
+        self.labels.get_or_init(|| {
+            let size =
+                self.clusters.iter().flatten()
+                    .map(|index| index + 1)
+                    .fold(self.similarity_matrix.size(), Size::max);
+            let mut labels = vec![None; size];
+            for (position, cluster) in self.clusters.iter().enumerate() {
+                for &index in cluster {
+                    labels[index] = Some(position);
+                }
+            }
+            labels
+        })
+    }
+
+    /// Discard the cached labels so that the next call to `labels` or `cluster_of` rebuilds them
+    /// from the current `clusters`. Required after mutating `clusters` or `noise` directly.
+    pub fn reset_labels(&mut self) {
+        self.labels = OnceLock::new();
+    }
+
+    /// Find the cluster containing an element in constant time, after `labels` is first built.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The element's index in the input set.
+    ///
+    /// # Return
+    ///
+    /// The position of the element's cluster in `clusters` or `None` if the element is noise or
+    /// out of range.
+    pub fn cluster_of(&self, index: Index) -> Option<usize> {
+        self.labels().get(index).copied().flatten()
+    }
+
+    /// Separate genuine, multi-element clusters from singleton elements that matched nothing.
+    ///
+    /// # Return
+    ///
+    /// A pair containing the clusters having two or more elements and the flat list of singleton
+    /// indices.
+    pub fn partition(&self) -> (Vec<Cluster>, Vec<Index>) {
+        let (clusters, singletons): (Vec<&Cluster>, Vec<&Cluster>) =
+            self.clusters.iter()
+                .partition(|cluster| cluster.len() > 1);
+
+        (
+            clusters.into_iter().cloned().collect::<Vec<Cluster>>(),
+            singletons.into_iter().map(|cluster| cluster[0]).collect::<Vec<Index>>(),
+        )
+    }
+
+    /// Check that every index in `0..size` appears exactly once across all clusters and noise.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the clustered input set.
+    ///
+    /// # Return
+    ///
+    /// `Ok` if this result is a proper partition or the first violation found otherwise.
+    pub fn validate(&self, size: Size) -> Result<(), ClusterError> {
+        let mut seen = vec![false; size];
+
+        for &index in self.clusters.iter().flatten().chain(self.noise.iter()) {
+            if index >= size {
+                return Err(ClusterError::OutOfRange { index, size });
+            }
+            if seen[index] {
+                return Err(ClusterError::Duplicate { index });
+            }
+            seen[index] = true;
+        }
+
+        match seen.iter().position(|seen| !seen) {
+            Some(index) => Err(ClusterError::Missing { index }),
+            None => Ok(()),
+        }
+    }
+
+    /// Find the elements belonging to more than one cluster, which a proper partition never
+    /// holds. This audits results whose clusters were edited or may overlap.
+    ///
+    /// # Return
+    ///
+    /// Each shared element mapped to the ascending positions of the clusters holding it; empty
+    /// for a partition.
+    pub fn shared_members(&self) -> HashMap<Index, Vec<usize>> {
+        let mut memberships: HashMap<Index, Vec<usize>> = HashMap::new();
+        for (position, cluster) in self.clusters.iter().enumerate() {
+            for &index in cluster {
+                let positions = memberships.entry(index).or_default();
+                if positions.last() != Some(&position) {
+                    positions.push(position);
+                }
+            }
+        }
+
+        memberships.retain(|_, positions| positions.len() > 1);
+        memberships
+    }
+
+    /// Compare this clustering to another clustering of the same input set.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The clustering to compare to.
+    ///
+    /// # Return
+    ///
+    /// The pairs split or merged going from this clustering to `other`, and the elements moved.
+    pub fn diff(&self, other: &ClusteringResult) -> ClusterDiff {
+        let split_pairs = self.pairs_not_clustered_in(other);
+        let merged_pairs = other.pairs_not_clustered_in(self);
+
+        let mut moved =
+            split_pairs.iter().chain(merged_pairs.iter())
+                .flat_map(|(i, j)| vec![*i, *j])
+                .collect::<Vec<Index>>();
+        moved.sort_unstable();
+        moved.dedup();
+
+        ClusterDiff { split_pairs, merged_pairs, moved }
+    }
+
+    /// Collect, in ascending order, the pairs sharing a cluster here but not in another clustering.
+    fn pairs_not_clustered_in(&self, other: &ClusteringResult) -> Vec<IndexPair> {
+        let mut pairs = Vec::new();
+        for cluster in &self.clusters {
+            let mut members = cluster.clone();
+            members.sort_unstable();
+            for (position, &i) in members.iter().enumerate() {
+                for &j in &members[position + 1..] {
+                    let together = matches!(
+                        (other.cluster_of(i), other.cluster_of(j)),
+                        (Some(cluster_i), Some(cluster_j)) if cluster_i == cluster_j
+                    );
+                    if !together {
+                        pairs.push((i, j));
+                    }
+                }
+            }
+        }
+
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Iterate over this result's clusters without cloning them.
+    pub fn iter_clusters(&self) -> impl Iterator<Item=&[Index]> {
+        self.clusters.iter().map(|cluster| cluster.as_slice())
+    }
+
+    /// Refine this clustering agglomeratively: repeatedly merge the two clusters with the highest
+    /// average linkage as long as doing so improves an evaluator's score by at least `min_gain`.
+    /// This complements the clusterer's partitive pass, trading compute for quality. Noise is
+    /// left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_iters` - The maximum number of merges.
+    /// * `min_gain` - The minimum evaluation improvement for a merge to be accepted.
+    /// * `evaluator` - The evaluator judging each merge.
+    ///
+    /// # Return
+    ///
+    /// The number of merges performed. Refinement stops at the first rejected merge.
+    pub fn refine(&mut self, max_iters: usize, min_gain: f64, evaluator: &impl ClusterEvaluator) -> usize {
+        let mut evaluation = evaluator.evaluate(self);
+
+        for merges in 0..max_iters {
+            let (position_1, position_2) = match self.closest_clusters() {
+                Some(positions) => positions,
+                None => return merges,
+            };
+
+            let previous_clusters = self.clusters.clone();
+            let merged = self.clusters.remove(position_2);
+            self.clusters[position_1].extend(merged);
+            self.reset_labels();
+
+            let merged_evaluation = evaluator.evaluate(self);
+            let improves =
+                evaluator.best_of(merged_evaluation, evaluation) &&
+                    (merged_evaluation - evaluation).abs() >= min_gain;
+            if !improves {
+                self.clusters = previous_clusters;
+                self.reset_labels();
+                return merges;
+            }
+            evaluation = merged_evaluation;
+        }
+
+        max_iters
+    }
+
+    /// Find the two clusters with the highest average linkage: the sum of the similarities
+    /// between their members divided by the number of member pairs.
+    ///
+    /// # Return
+    ///
+    /// The ascending positions of the closest clusters, the lowest among equally close ones, or
+    /// `None` if no two clusters are linked at all.
+    fn closest_clusters(&self) -> Option<(usize, usize)> {
+        let labels = self.labels();
+
+        let mut linkage_sums: HashMap<(usize, usize), Similarity> = HashMap::new();
+        for (index, row) in self.similarity_matrix.rows.iter().enumerate() {
+            let label = match labels.get(index).copied().flatten() {
+                Some(label) => label,
+                None => continue,
+            };
+            for score in row.scores.iter().filter(|score| score.sibling_index > index) {
+                if let Some(sibling_label) = labels.get(score.sibling_index).copied().flatten() {
+                    if sibling_label != label {
+                        let key = (label.min(sibling_label), label.max(sibling_label));
+                        *linkage_sums.entry(key).or_insert(0.0) += score.similarity;
+                    }
+                }
+            }
+        }
+
+        linkage_sums.into_iter()
+            .map(|((position_1, position_2), sum)| {
+                let pair_count = self.clusters[position_1].len() * self.clusters[position_2].len();
+                ((position_1, position_2), sum / pair_count as f64)
+            })
+            .min_by(|(positions_1, linkage_1), (positions_2, linkage_2)| {
+                linkage_2.partial_cmp(linkage_1).unwrap().then(positions_1.cmp(positions_2))
+            })
+            .map(|(positions, _)| positions)
+    }
+
+    /// Find the clusters whose members aren't all connected through internal edges meeting a
+    /// threshold, as can happen when members are linked only through elements placed elsewhere.
+    /// Grappolo's own algorithm should never produce such clusters, so any found point to a bug.
+    ///
+    /// # Arguments
+    ///
+    /// * `min_similarity` - The minimum similarity of the edges connecting members.
+    ///
+    /// # Return
+    ///
+    /// The ascending positions of the disconnected clusters. Singletons are always connected.
+    pub fn verify_connected(&self, min_similarity: Similarity) -> Vec<usize> {
+        self.clusters.iter().enumerate()
+            .filter(|(_, cluster)| {
+                let members = cluster.iter().copied().collect::<HashSet<Index>>();
+
+                let mut reached = HashSet::new();
+                let mut pending = cluster.iter().take(1).copied().collect::<Vec<Index>>();
+                while let Some(index) = pending.pop() {
+                    if reached.insert(index) {
+                        pending.extend(
+                            self.similarity_matrix[index].scores.iter()
+                                .filter(|score| score.similarity >= min_similarity)
+                                .map(|score| score.sibling_index)
+                                .filter(|sibling| members.contains(sibling) && !reached.contains(sibling))
+                        );
+                    }
+                }
+
+                reached.len() < members.len()
+            })
+            .map(|(position, _)| position)
+            .collect::<Vec<usize>>()
+    }
+
+    /// Sum the similarities of each cluster's internal edges, counting each edge once. This
+    /// measures how much evidence holds a cluster together; singletons weigh `0.0`.
+    ///
+    /// # Return
+    ///
+    /// The internal weight of each cluster, in cluster order.
+    pub fn cluster_internal_weight(&self) -> Vec<Similarity> {
+        self.clusters.iter()
+            .map(|cluster| {
+                let members = cluster.iter().copied().collect::<HashSet<Index>>();
+                cluster.iter()
+                    .flat_map(|index|
+                        self.similarity_matrix[*index].scores.iter()
+                            .filter(move |score| score.sibling_index > *index))
+                    .filter(|score| members.contains(&score.sibling_index))
+                    .map(|score| score.similarity)
+                    .sum::<Similarity>()
+            })
+            .collect::<Vec<Similarity>>()
+    }
+
+    /// Find each cluster's medoid: the member with the highest summed similarity to the rest of
+    /// its cluster. Ties go to the member listed first; singletons are their own medoid.
+    ///
+    /// # Return
+    ///
+    /// The medoid of each cluster, in cluster order.
+    pub fn medoids(&self) -> Vec<Index> {
+        self.clusters.iter()
+            .map(|cluster| {
+                let members = cluster.iter().copied().collect::<HashSet<Index>>();
+                let mut medoid = (cluster[0], Similarity::NEG_INFINITY);
+                for &index in cluster {
+                    let similarity_sum =
+                        self.similarity_matrix[index].scores.iter()
+                            .filter(|score| members.contains(&score.sibling_index))
+                            .map(|score| score.similarity)
+                            .sum::<Similarity>();
+                    if similarity_sum > medoid.1 {
+                        medoid = (index, similarity_sum);
+                    }
+                }
+                medoid.0
+            })
+            .collect::<Vec<Index>>()
+    }
+
+    /// Write this result as CSV rows of `element,label`, one per element in index order, as
+    /// expected by pandas and scikit-learn. Labels are cluster positions as per `labels`; noise
+    /// is labeled `-1`, following scikit-learn's convention.
+    ///
+    /// # Arguments
+    ///
+    /// * `w` - The writer to emit CSV to.
+    /// * `header` - Whether to emit a header row first.
+    /// * `names` - The elements' original strings, if any, emitted as a third `name` column.
+    ///
+    /// # Return
+    ///
+    /// The result of writing.
+    pub fn write_label_csv<W: Write>(&self, w: &mut W, header: bool, names: Option<&[String]>) -> io::Result<()> {
+        if header {
+            match names {
+                Some(_) => writeln!(w, "element,label,name")?,
+                None => writeln!(w, "element,label")?,
+            }
+        }
+
+        for (index, label) in self.labels().iter().enumerate() {
+            let label = label.map_or(-1, |label| label as i64);
+            match names {
+                Some(names) => writeln!(w, "{},{},{}", index, label, csv_escape(&names[index]))?,
+                None => writeln!(w, "{},{}", index, label)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Iteration over a result's clusters, consuming the result. Noise and the matrix are dropped.
+impl IntoIterator for ClusteringResult {
+    type Item = Cluster;
+    type IntoIter = std::vec::IntoIter<Cluster>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.clusters.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ClusteringResult {
+    type Item = &'a Cluster;
+    type IntoIter = std::slice::Iter<'a, Cluster>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.clusters.iter()
+    }
+}
+
+/// Quote a CSV field if it holds a comma, quote or line break, doubling any embedded quotes.
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// The differences between two clusterings of the same input set, as changes in which pairs of
+/// elements share a cluster.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClusterDiff {
+    /// Pairs sharing a cluster in the first clustering but not in the second, in ascending order.
+    pub split_pairs: Vec<IndexPair>,
+    /// Pairs sharing a cluster in the second clustering but not in the first, in ascending order.
+    pub merged_pairs: Vec<IndexPair>,
+    /// Elements in any split or merged pair, whose cluster mates thus changed, in ascending order.
+    pub moved: Vec<Index>,
+}
+
+impl ClusterDiff {
+    /// Ascertain whether both clusterings group elements identically.
+    pub fn is_empty(&self) -> bool {
+        self.split_pairs.is_empty() && self.merged_pairs.is_empty()
+    }
+}
+
+impl fmt::Display for ClusterDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} pairs split, {} pairs merged, {} elements moved",
+            self.split_pairs.len(),
+            self.merged_pairs.len(),
+            self.moved.len(),
+        )
+    }
+}
+
+/// Policy applied when a sibling is exactly as similar to the current seed as it is to the seed
+/// of the (previously committed) cluster that already claimed it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Leave the sibling in the cluster whose seed was processed first. This is the default.
+    FirstSeed,
+    /// Move the sibling to the current cluster if it's larger than the one holding the sibling.
+    PreferLarger,
+    /// Move the sibling to the current cluster if it's smaller than the one holding the sibling.
+    PreferSmaller,
+}
+
+/// Condition on a cluster's size for it to be recursively split. Clusters spanning the entire
+/// matrix being clustered, as well as clusters of fewer than three elements, are never split.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitTrigger {
+    /// Split clusters having at least this many elements. The default is `Absolute(3)`.
+    Absolute(Size),
+    /// Split clusters having more elements than this fraction of the matrix being clustered,
+    /// which makes splitting behave alike across input sets of very different sizes.
+    Relative(f64),
+}
+
+/// Method for splitting a cluster meeting the `SplitTrigger`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitStrategy {
+    /// Re-run the clustering algorithm on the cluster's elements. This is the default.
+    Recursive,
+    /// Bisect the cluster across its lowest-similarity edge: each element joins whichever end of
+    /// that edge it's more similar to (the first end on ties). Unlike complete linkage, only the
+    /// element's similarity to each end counts, not its similarity to the rest of either half.
+    WeakestEdgeBisect,
+    /// Break the cluster's single-linkage spanning tree at its weakest link, yielding the
+    /// connected components left once the lowest-similarity links are removed.
+    SingleLinkageComponents,
+}
+
+/// Configuration options for the `Clusterer`.
+///
+/// Must-link and cannot-link constraints refer to indices of the clustered matrix. Cannot-link
+/// constraints are honored while growing clusters: an element is never added to a cluster holding
+/// any element it cannot link with. Must-link constraints are enforced afterwards by merging the
+/// clusters holding each must-linked pair. When the two conflict, cannot-link takes precedence:
+/// a must-link merge that would place a cannot-linked pair in the same cluster is skipped.
+///
+/// A maximum cluster size is enforced last, so it takes precedence over must-link constraints.
+/// Each oversized cluster is re-clustered after dropping its weakest similarity value, and any
+/// resulting cluster still over the cap is re-split the same way. Every round strictly reduces
+/// the number of distinct similarity values involved, so splitting always terminates: at worst,
+/// a cluster is broken into singletons.
+#[derive(Clone, Debug)]
+pub struct ClustererConfig {
+    /// Policy for assigning siblings equally similar to two clusters.
+    pub tie_break: TieBreak,
+    /// Pairs of elements to be placed in the same cluster regardless of their similarity.
+    pub must_link: Vec<(Index, Index)>,
+    /// Pairs of elements never to be placed in the same cluster.
+    pub cannot_link: Vec<(Index, Index)>,
+    /// The maximum number of elements in a cluster, if any.
+    pub max_cluster_size: Option<Size>,
+    /// The condition for recursively splitting a cluster.
+    pub split_trigger: SplitTrigger,
+    /// The method for splitting a cluster.
+    pub split_strategy: SplitStrategy,
+    /// Minimum weight, the sum of an element's similarities to the other members, for an element
+    /// to take part in splitting its cluster. Weaker elements are stripped from the cluster
+    /// before it's split and end up as singletons. `None` strips nothing.
+    pub min_node_weight: Option<Similarity>,
+    /// How to rank elements when picking cluster seeds.
+    pub rank_mode: RankMode,
+    /// Whether to split clusters at all. Disabling splitting keeps only the partitive phase,
+    /// committing each seed's cluster as is, which helps gauge what the splitting phase changes.
+    /// `max_cluster_size` is still enforced.
+    pub split: bool,
+    /// Seed for the random number generator of randomized strategies, making their results
+    /// reproducible; `None` draws from entropy. All current strategies are deterministic and
+    /// ignore it, but randomized ones must honor it.
+    pub seed: Option<u64>,
+    /// Elements, such as placeholder values, never to be clustered with anything. They seed no
+    /// cluster, join no cluster and end up as singletons, overriding must-link constraints.
+    pub stop_indices: HashSet<Index>,
+    /// Per-element minimum similarity for a sibling to join the cluster seeded by that element,
+    /// letting noisy elements demand closer siblings than others. There must be one threshold per
+    /// element. `None` applies no threshold beyond the matrix's own minimum similarity.
+    pub neighbor_thresholds: Option<Vec<Similarity>>,
+    /// Minimum similarity, exclusive, for a singleton left over by clustering to be attached to
+    /// the cluster of its most similar neighbor, unless that neighbor is itself alone. `None` keeps
+    /// all singletons.
+    pub absorb_singletons_above: Option<Similarity>,
+}
+
+impl Default for ClustererConfig {
+    fn default() -> ClustererConfig {
+        ClustererConfig {
+            tie_break: TieBreak::FirstSeed,
+            must_link: Vec::new(),
+            cannot_link: Vec::new(),
+            max_cluster_size: None,
+            split_trigger: SplitTrigger::Absolute(3),
+            split_strategy: SplitStrategy::Recursive,
+            min_node_weight: None,
+            rank_mode: RankMode::ByWeightedDegree,
+            split: true,
+            seed: None,
+            stop_indices: HashSet::new(),
+            neighbor_thresholds: None,
+            absorb_singletons_above: None,
+        }
+    }
+}
+
+impl ClustererConfig {
+    /// Return the minimum similarity of the siblings joining a cluster seeded by an element.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The seed's index.
+    ///
+    /// # Return
+    ///
+    /// The seed's neighbor threshold, or `0.0` if there's none.
+    pub fn neighbor_threshold(&self, index: Index) -> Similarity {
+        self.neighbor_thresholds.as_ref()
+            .and_then(|thresholds| thresholds.get(index).copied())
+            .unwrap_or(0.0)
+    }
+
+    /// Assert that must-link and cannot-link constraints refer to elements of the graph being
+    /// clustered and that there's a neighbor threshold for each element, if any, as mismatches
+    /// would otherwise fail deep inside clustering or go unnoticed.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the graph being clustered.
+    fn assert_fits(&self, size: Size) {
+        for &(index_1, index_2) in self.must_link.iter().chain(&self.cannot_link) {
+            assert!(index_1 < size && index_2 < size,
+                    "Constraint ({}, {}) out of range for matrix of size {}", index_1, index_2, size);
+        }
+        if let Some(thresholds) = &self.neighbor_thresholds {
+            assert_eq!(thresholds.len(), size, "Neighbor thresholds must match matrix size");
+        }
+    }
+
+    /// Return the configuration used for recursively splitting a cluster. Constraints and stop
+    /// indices are dropped because they're expressed in top-level indices and the split cluster
+    /// already honors cannot-link constraints (as does any subset of it) and holds no stop
+    /// index. The maximum cluster size is dropped because it's enforced on the top-level
+    /// clusters, as is singleton absorption. Neighbor thresholds are dropped as well;
+    /// `split_config` carries them over to the split cluster's indices.
+    fn inner_config(&self) -> ClustererConfig {
+        ClustererConfig {
+            tie_break: self.tie_break,
+            must_link: Vec::new(),
+            cannot_link: Vec::new(),
+            max_cluster_size: None,
+            split_trigger: self.split_trigger,
+            split_strategy: self.split_strategy,
+            min_node_weight: self.min_node_weight,
+            rank_mode: self.rank_mode,
+            split: self.split,
+            seed: self.seed,
+            stop_indices: HashSet::new(),
+            neighbor_thresholds: None,
+            absorb_singletons_above: None,
+        }
+    }
+
+    /// Return the configuration used for recursively splitting a cluster, with neighbor
+    /// thresholds renumbered to the cluster's member positions.
+    ///
+    /// # Arguments
+    ///
+    /// * `members` - The split cluster's members, in the order of the split sub-matrix.
+    fn split_config(&self, members: &[Index]) -> ClustererConfig {
+        ClustererConfig {
+            neighbor_thresholds:
+            self.neighbor_thresholds.as_ref().map(|_| {
+                members.iter().map(|index| self.neighbor_threshold(*index)).collect()
+            }),
+            ..self.inner_config()
+        }
+    }
+}
+
+/// Events reported by a traced clustering run, useful for diagnosing how each element ended up in
+/// its cluster. Indices always refer to the matrix passed to the clusterer, even while recursively
+/// splitting spun-off sub-matrices.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceEvent {
+    /// An element was picked as the seed of a new cluster.
+    SeedPicked { index: Index },
+    /// An element was added to the current cluster.
+    SiblingAdded { index: Index },
+    /// A cluster was committed as part of the final result.
+    ClusterCommitted { members: Vec<Index> },
+    /// The current cluster is being recursively split.
+    RecursingInto { size: Size },
+}
+
+/// Destination for trace events along with the mapping from the indices of the matrix being
+/// clustered to those of the top-level matrix.
+struct Trace<'a> {
+    emit: &'a mut dyn FnMut(TraceEvent),
+    global_indices: Vec<Index>,
+}
+
+impl<'a> Trace<'a> {
+    fn global(&self, indices: &[Index]) -> Vec<Index> {
+        indices.iter()
+            .map(|index| self.global_indices[*index])
+            .collect::<Vec<Index>>()
+    }
+}
+
+pub struct Clusterer {
+    config: ClustererConfig,
+    clusters_so_far: Vec<Cluster>,
+    visited_so_far: HashSet<Index>,
+    current_cluster: Vec<Index>,
+    /// Similarity with which each non-seed element was claimed. Only tracked for tie-breaking.
+    claimed_with: HashMap<Index, Similarity>,
+    /// Position in `clusters_so_far` of each committed element. Only tracked for tie-breaking.
+    assigned_to: HashMap<Index, usize>,
+    /// The elements each element cannot be clustered with.
+    cannot_link: HashMap<Index, HashSet<Index>>,
+    /// Elements to seed clusters before those ranked by weight.
+    anchors: Vec<Index>,
+    /// The instant past which no new top-level cluster is started.
+    deadline: Option<Instant>,
+    /// Whether the deadline interrupted the last clustering.
+    timed_out: bool,
+}
+
+impl Clusterer {
+    /// Cluster a similarity matrix using the default configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result.
+    pub fn cluster(similarity_matrix: SimilarityMatrix) -> ClusteringResult {
+        Clusterer::cluster_with(similarity_matrix, &ClustererConfig::default())
+    }
+
+    /// Cluster a similarity matrix seeding clusters from a set of anchor elements, in the given
+    /// order, before falling back to `rank_by_weight` for the remaining elements. Anchors thus
+    /// own the clusters pulling their similar siblings. An anchor already pulled into a previous
+    /// anchor's cluster seeds no cluster of its own and, as with any seed, an anchor's cluster may
+    /// still be recursively split.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `config` - The clusterer configuration.
+    /// * `anchors` - The elements to seed clusters first.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result.
+    pub fn cluster_with_anchors(
+        similarity_matrix: SimilarityMatrix,
+        config: &ClustererConfig,
+        anchors: &[Index],
+    ) -> ClusteringResult {
+        assert!(anchors.iter().all(|anchor| *anchor < similarity_matrix.size()), "Anchor out of range");
+
+        let mut clusterer = Clusterer::new(config);
+        clusterer.anchors = anchors.to_vec();
+
+        let clusters = clusterer.collect_clusters(&similarity_matrix);
+
+        let result = ClusteringResult::new(clusters, vec![], similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+
+    /// Cluster a similarity matrix using a given configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `config` - The clusterer configuration.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result.
+    pub fn cluster_with(similarity_matrix: SimilarityMatrix, config: &ClustererConfig) -> ClusteringResult {
+        let mut clusterer = Clusterer::new(config);
+
+        let clusters = clusterer.collect_clusters(&similarity_matrix);
+
+        let result = ClusteringResult::new(clusters, vec![], similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+
+    /// Cluster a subset of a similarity matrix's elements using the default configuration,
+    /// sparing callers from mapping the spun-off matrix's indices back to the original ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix holding the subset.
+    /// * `indices` - The distinct elements to cluster, each less than the matrix's `size`.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result, whose clusters hold the global indices passed in and whose noise
+    /// holds the elements outside the subset, in ascending order, so the result still partitions
+    /// the whole matrix. Its matrix keeps the original size and global indices but holds only the
+    /// edges between subset elements.
+    pub fn cluster_subset(similarity_matrix: &SimilarityMatrix, indices: &[Index]) -> ClusteringResult {
+        assert!(indices.iter().all(|index| *index < similarity_matrix.size()), "Index out of range");
+        assert_eq!(
+            indices.iter().collect::<HashSet<&Index>>().len(), indices.len(),
+            "Subset indices must be distinct",
+        );
+
+        let min_similarity = similarity_matrix.min_similarity();
+        let clusters =
+            Clusterer::cluster_drop_matrix(similarity_matrix.spin_off(indices, min_similarity)).into_iter()
+                .map(|cluster| cluster.into_iter().map(|position| indices[position]).collect())
+                .collect::<Vec<Cluster>>();
+
+        let subset = indices.iter().copied().collect::<HashSet<Index>>();
+        let similarity_triplets =
+            indices.iter()
+                .flat_map(|&index| {
+                    similarity_matrix[index].scores.iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .filter(|score| subset.contains(&score.sibling_index))
+                        .map(move |score| (index, score.sibling_index, score.similarity))
+                })
+                .collect::<Vec<(Index, Index, Similarity)>>();
+        let subset_matrix =
+            SimilarityMatrix::from_triplets(similarity_matrix.size(), min_similarity, similarity_triplets);
+
+        let noise =
+            (0..similarity_matrix.size())
+                .filter(|index| !subset.contains(index))
+                .collect::<Vec<Index>>();
+
+        let result = ClusteringResult::new(clusters, noise, subset_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+
+    /// Cluster a similarity matrix starting from an existing partial clustering, which is kept
+    /// fixed: only elements outside it are placed. Each such element joins the existing cluster
+    /// of its most similar neighbor if that neighbor is already clustered; the remaining
+    /// elements are clustered among themselves using the default configuration. This supports
+    /// placing newly arrived elements without re-clustering the whole input set.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `existing` - The clusters to keep, holding distinct indices less than the matrix's size.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result. Existing clusters come first, in their original positions and
+    /// possibly extended with new elements, followed by the new clusters.
+    pub fn cluster_warm(similarity_matrix: SimilarityMatrix, existing: &[Cluster]) -> ClusteringResult {
+        let mut clusters = existing.to_vec();
+
+        let mut positions = vec![None; similarity_matrix.size()];
+        for (position, cluster) in clusters.iter().enumerate() {
+            for &index in cluster {
+                assert!(index < similarity_matrix.size(), "Index {} out of range", index);
+                assert!(positions[index].is_none(), "Index {} in more than one existing cluster", index);
+                positions[index] = Some(position);
+            }
+        }
+
+        let mut remaining = Vec::new();
+        for index in 0..similarity_matrix.size() {
+            if positions[index].is_some() {
+                continue;
+            }
+
+            let best_neighbor =
+                similarity_matrix.neighbors(index).into_iter()
+                    .min_by(|(index_1, similarity_1), (index_2, similarity_2)| {
+                        similarity_2.partial_cmp(similarity_1).unwrap().then(index_1.cmp(index_2))
+                    });
+            match best_neighbor.and_then(|(neighbor, _)| positions[neighbor]) {
+                Some(position) => clusters[position].push(index),
+                None => remaining.push(index),
+            }
+        }
+
+        if !remaining.is_empty() {
+            let remaining_matrix = similarity_matrix.spin_off(&remaining, similarity_matrix.min_similarity());
+            clusters.extend(
+                Clusterer::cluster_drop_matrix(remaining_matrix).into_iter()
+                    .map(|cluster| cluster.into_iter().map(|position| remaining[position]).collect::<Cluster>())
+            );
+        }
+
+        let result = ClusteringResult::new(clusters, vec![], similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+
+    /// Cluster a similarity matrix using a given configuration, stopping once a deadline passes. The deadline is checked before seeding each top-level cluster, so the cluster in
+    /// progress (including its recursive split) is always completed. Elements not yet visited
+    /// when the deadline passes are returned as singletons.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `config` - The clusterer configuration.
+    /// * `deadline` - The instant past which no new cluster is started.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result and whether clustering finished before the deadline.
+    pub fn cluster_with_deadline(
+        similarity_matrix: SimilarityMatrix,
+        config: &ClustererConfig,
+        deadline: Instant,
+    ) -> (ClusteringResult, bool) {
+        let mut clusterer = Clusterer::new(config);
+        clusterer.deadline = Some(deadline);
+
+        let clusters = clusterer.collect_clusters(&similarity_matrix);
+
+        let result = ClusteringResult::new(clusters, vec![], similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        (result, !clusterer.timed_out)
+    }
+
+    /// Cluster a similarity matrix using the default configuration, freeing the matrix before
+    /// returning. This lowers peak memory when only the clusters are needed, at the cost of the
+    /// `ClusteringResult` APIs (such as evaluators) that rely on the carried matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    ///
+    /// # Return
+    ///
+    /// The collected clusters.
+    pub fn cluster_drop_matrix(similarity_matrix: SimilarityMatrix) -> Vec<Cluster> {
+        let clusters = Clusterer::new(&ClustererConfig::default()).run(&similarity_matrix);
+        drop(similarity_matrix);
+        clusters
+    }
+
+    /// Cluster a similarity matrix reporting each clustering decision to a trace function.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `config` - The clusterer configuration.
+    /// * `trace` - The function receiving trace events.
+    ///
+    /// # Return
+    ///
+    /// The `Clustering` result.
+    pub fn cluster_traced(
+        similarity_matrix: SimilarityMatrix,
+        config: &ClustererConfig,
+        trace: &mut dyn FnMut(TraceEvent),
+    ) -> ClusteringResult {
+        let mut trace = Trace {
+            emit: trace,
+            global_indices: (0..similarity_matrix.size()).collect::<Vec<Index>>(),
+        };
+
+        let mut clusterer = Clusterer::new(config);
+
+        let clusters = clusterer.collect_traced_clusters(&similarity_matrix, Some(&mut trace));
+
+        let result = ClusteringResult::new(clusters, vec![], similarity_matrix);
+        debug_assert_eq!(result.validate(result.similarity_matrix.size()), Ok(()));
+        result
+    }
+
+    /// Create a new, reusable `Clusterer` for a given configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The clusterer configuration.
+    pub fn new(config: &ClustererConfig) -> Clusterer {
+        let mut cannot_link: HashMap<Index, HashSet<Index>> = HashMap::new();
+        for &(index_1, index_2) in &config.cannot_link {
+            cannot_link.entry(index_1).or_default().insert(index_2);
+            cannot_link.entry(index_2).or_default().insert(index_1);
+        }
+
+        Clusterer {
+            config: config.clone(),
+            clusters_so_far: Vec::new(),
+            visited_so_far: HashSet::new(),
+            current_cluster: Vec::new(),
+            claimed_with: HashMap::new(),
+            assigned_to: HashMap::new(),
+            cannot_link,
+            anchors: Vec::new(),
+            deadline: None,
+            timed_out: false,
+        }
+    }
+
+    /// Cluster a similarity matrix, or any other `Graph`, reusing this clusterer's allocations.
+    /// Clustering many small matrices (such as one per connected component) with a single
+    /// clusterer avoids reallocating its internal state for each of them. Graphs not backed by a
+    /// matrix are only materialized for the clusters being recursively split.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - Similarity matrix or graph to cluster.
+    ///
+    /// # Return
+    ///
+    /// Collected clusters.
+    pub fn run<G: Graph>(&mut self, graph: &G) -> Vec<Cluster> {
+        self.reset();
+        self.collect_clusters(graph)
+    }
+
+    /// Clear this clusterer's state while retaining its allocated capacity.
+    pub fn reset(&mut self) {
+        self.clusters_so_far.clear();
+        self.visited_so_far.clear();
+        self.current_cluster.clear();
+        self.claimed_with.clear();
+        self.assigned_to.clear();
+        self.timed_out = false;
+    }
+
+    /// Visit and collect siblings from a given element. Long resulting clusters are recursively split.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - Reference to the similarity graph to use for traversal.
+    ///
+    /// # Return
+    ///
+    /// Collected clusters.
+    fn collect_clusters<G: Graph>(&mut self, graph: &G) -> Vec<Cluster> {
+        self.collect_traced_clusters(graph, None)
+    }
+
+    /// Collect clusters as per `collect_clusters`, optionally reporting each decision to a trace.
+    fn collect_traced_clusters<G: Graph>(
+        &mut self,
+        graph: &G,
+        mut trace: Option<&mut Trace>,
+    ) -> Vec<Cluster> {
+        self.config.assert_fits(graph.size());
+
+        let ranked_indices =
+            self.anchors.iter()
+                .copied()
+                .chain(graph.rank_by_weight(self.config.rank_mode))
+                .collect::<Vec<Index>>();
+
+        let mut stop_indices =
+            self.config.stop_indices.iter()
+                .copied()
+                .filter(|index| *index < graph.size())
+                .collect::<Vec<Index>>();
+        stop_indices.sort_unstable();
+        self.visited_so_far.extend(stop_indices.iter().copied());
+
+        for current_index in ranked_indices {
+            if self.can_add(current_index) {
+                if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    self.timed_out = true;
+                    break;
+                }
+
+                self.new_cluster(current_index);
+
+                let row = graph.row(current_index);
+                let siblings = match self.config.neighbor_thresholds {
+                    None => row.ranked_siblings(self.to_be_excluded()),
+                    Some(_) =>
+                        row.cut_at(self.config.neighbor_threshold(current_index)).into_iter()
+                            .filter(|sibling| !self.to_be_excluded().contains(sibling))
+                            .collect::<Vec<Index>>(),
+                };
+
+                for sibling in siblings {
+                    self.add_to_cluster(sibling);
+                }
+
+                if self.breaks_ties() {
+                    self.resolve_ties(current_index, &row);
+                }
+
+                if let Some(trace) = trace.as_mut() {
+                    let members = trace.global(&self.current_cluster);
+                    (trace.emit)(TraceEvent::SeedPicked { index: members[0] });
+                    for index in members.into_iter().skip(1) {
+                        (trace.emit)(TraceEvent::SiblingAdded { index });
+                    }
+                }
+
+                if !self.needs_split(graph.size()) {
+                    if let Some(trace) = trace.as_mut() {
+                        let members = trace.global(&self.current_cluster);
+                        (trace.emit)(TraceEvent::ClusterCommitted { members });
+                    }
+
+                    self.commit_current_cluster();
+                } else {
+                    let mut similarity_matrix = graph.subgraph(&self.current_cluster);
+                    if let Some(min_node_weight) = self.config.min_node_weight {
+                        let (retained_matrix, stripped) = self.strip_weak_members(similarity_matrix, min_node_weight);
+                        similarity_matrix = retained_matrix;
+
+                        if let Some(trace) = trace.as_mut() {
+                            for index in trace.global(&stripped) {
+                                (trace.emit)(TraceEvent::ClusterCommitted { members: vec![index] });
+                            }
+                        }
+                        if self.current_cluster.is_empty() {
+                            continue;
+                        }
+                    }
+
+                    let inner_clusters = match self.config.split_strategy {
+                        SplitStrategy::Recursive => {
+                            let mut clusterer = Clusterer::new(&self.config.split_config(&self.current_cluster));
+                            match trace.as_mut() {
+                                None => clusterer.collect_clusters(&similarity_matrix),
+                                Some(trace) => {
+                                    (trace.emit)(TraceEvent::RecursingInto { size: self.current_cluster_len() });
+
+                                    let global_indices = trace.global(&self.current_cluster);
+                                    let mut inner_trace = Trace { emit: &mut *trace.emit, global_indices };
+                                    clusterer.collect_traced_clusters(&similarity_matrix, Some(&mut inner_trace))
+                                }
+                            }
+                        }
+                        SplitStrategy::WeakestEdgeBisect => Clusterer::bisect(&similarity_matrix),
+                        SplitStrategy::SingleLinkageComponents => Clusterer::break_weakest_link(&similarity_matrix),
+                    };
+
+                    if self.config.split_strategy != SplitStrategy::Recursive {
+                        if let Some(trace) = trace.as_mut() {
+                            for inner_cluster in &inner_clusters {
+                                let local_members =
+                                    inner_cluster.iter()
+                                        .map(|inner_index| self.current_cluster[*inner_index])
+                                        .collect::<Vec<Index>>();
+                                let members = trace.global(&local_members);
+                                (trace.emit)(TraceEvent::ClusterCommitted { members });
+                            }
+                        }
+                    }
+
+                    self.commit_inner_clusters(inner_clusters);
+                }
+            }
+        }
+
+        if self.timed_out {
+            for index in 0..graph.size() {
+                if self.can_add(index) {
+                    self.visited_so_far.insert(index);
+                    self.clusters_so_far.push(vec![index]);
+                }
+            }
+        }
+
+        self.clusters_so_far.extend(stop_indices.into_iter().map(|index| vec![index]));
+
+        if !self.config.must_link.is_empty() {
+            self.enforce_must_links();
+        }
+
+        if let Some(floor) = self.config.absorb_singletons_above {
+            self.absorb_singletons(graph, floor);
+        }
+
+        let may_leave_empty_clusters =
+            self.breaks_ties() || !self.config.must_link.is_empty() || self.config.absorb_singletons_above.is_some();
+        if may_leave_empty_clusters {
+            self.clusters_so_far.retain(|cluster| !cluster.is_empty());
+        }
+
+        if let Some(max_cluster_size) = self.config.max_cluster_size {
+            self.enforce_max_cluster_size(graph, max_cluster_size);
+        }
+
+        mem::take(&mut self.clusters_so_far)
+    }
+
+    /// Replace each cluster exceeding `max_cluster_size` by its forced split.
+    fn enforce_max_cluster_size<G: Graph>(&mut self, graph: &G, max_cluster_size: Size) {
+        let inner_config = self.config.inner_config();
+
+        self.clusters_so_far =
+            mem::take(&mut self.clusters_so_far).into_iter()
+                .flat_map(|cluster|
+                    Clusterer::cap_cluster(graph, cluster, &inner_config, max_cluster_size))
+                .collect::<Vec<Cluster>>();
+    }
+
+    /// Return a cluster as is if within `max_cluster_size` or its forced split otherwise.
+    fn cap_cluster<G: Graph>(
+        graph: &G,
+        cluster: Cluster,
+        config: &ClustererConfig,
+        max_cluster_size: Size,
+    ) -> Vec<Cluster> {
+        if cluster.len() <= max_cluster_size {
+            return vec![cluster];
+        }
+
+        let inner_matrix = graph.subgraph(&cluster);
+        Clusterer::force_split(&inner_matrix, config, max_cluster_size).into_iter()
+            .map(|sub_cluster|
+                sub_cluster.iter()
+                    .map(|inner_index| cluster[*inner_index])
+                    .collect::<Cluster>())
+            .collect::<Vec<Cluster>>()
+    }
+
+    /// Re-cluster all elements of a matrix without its weakest similarity value, capping the
+    /// resulting clusters in turn.
+    fn force_split(
+        similarity_matrix: &SimilarityMatrix,
+        config: &ClustererConfig,
+        max_cluster_size: Size,
+    ) -> Vec<Cluster> {
+        let size = similarity_matrix.size();
+        if similarity_matrix.similarity_values.len() < 2 {
+            return (0..size).map(|index| vec![index]).collect::<Vec<Cluster>>();
+        }
+
+        let indices = (0..size).collect::<Vec<Index>>();
+        let similarity_matrix = similarity_matrix.spin_off(&indices, similarity_matrix.similarity_values[1]);
+
+        Clusterer::new(config).collect_clusters(&similarity_matrix).into_iter()
+            .flat_map(|cluster| Clusterer::cap_cluster(&similarity_matrix, cluster, config, max_cluster_size))
+            .collect::<Vec<Cluster>>()
+    }
+
+    /// Bisect a cluster, given as a matrix of its elements, across its lowest-similarity edge.
+    fn bisect(similarity_matrix: &SimilarityMatrix) -> Vec<Cluster> {
+        let weakest_edge =
+            similarity_matrix.rows.iter()
+                .enumerate()
+                .flat_map(|(index, row)|
+                    row.scores.iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .map(move |score| (index, score.sibling_index, score.similarity)))
+                .min_by(|(row_1, column_1, similarity_1), (row_2, column_2, similarity_2)|
+                    similarity_1.partial_cmp(similarity_2)
+                        .unwrap_or(Ordering::Equal)
+                        .then((row_1, column_1).cmp(&(row_2, column_2))));
+
+        let (first_end, second_end) = match weakest_edge {
+            None => return vec![(0..similarity_matrix.size()).collect::<Cluster>()],
+            Some((first_end, second_end, _)) => (first_end, second_end),
+        };
+
+        let (mut first_half, mut second_half) = (Vec::new(), Vec::new());
+        for index in 0..similarity_matrix.size() {
+            let row = &similarity_matrix[index];
+            if index == second_end || (index != first_end && row[second_end] > row[first_end]) {
+                second_half.push(index);
+            } else {
+                first_half.push(index);
+            }
+        }
+
+        vec![first_half, second_half]
+    }
+
+    /// Split a cluster, given as a matrix of its elements, by removing its single-linkage
+    /// spanning tree's weakest link.
+    fn break_weakest_link(similarity_matrix: &SimilarityMatrix) -> Vec<Cluster> {
+        let mut dendrogram = Clusterer::single_linkage(similarity_matrix);
+        if dendrogram.merges.len() + 1 == dendrogram.size {
+            dendrogram.merges.pop();
+        }
+
+        dendrogram.cut_at(0.0)
+    }
+
+    /// Merge the clusters holding each must-linked pair unless doing so would place a
+    /// cannot-linked pair in the same cluster. Merged-away clusters are left empty.
+    fn enforce_must_links(&mut self) {
+        let mut positions = HashMap::new();
+        for (position, cluster) in self.clusters_so_far.iter().enumerate() {
+            for index in cluster {
+                positions.insert(*index, position);
+            }
+        }
+
+        for &(index_1, index_2) in &self.config.must_link {
+            if self.config.stop_indices.contains(&index_1) || self.config.stop_indices.contains(&index_2) {
+                continue;
+            }
+
+            let (position_1, position_2) = (positions[&index_1], positions[&index_2]);
+            if position_1 == position_2 {
+                continue;
+            }
+
+            let conflicts =
+                self.clusters_so_far[position_2].iter()
+                    .any(|index| !self.can_join(*index, &self.clusters_so_far[position_1]));
+            if conflicts {
+                continue;
+            }
+
+            let moved = mem::take(&mut self.clusters_so_far[position_2]);
+            for index in &moved {
+                positions.insert(*index, position_1);
+            }
+            self.clusters_so_far[position_1].extend(moved);
+        }
+    }
+
+    /// Attach each singleton to the cluster holding its single most similar neighbor (the lowest
+    /// index among equally similar ones) if their similarity exceeds a floor. A singleton whose
+    /// best neighbor is itself alone, such as another singleton or a stop index, stays alone, as
+    /// does one that cannot link with the neighbor's cluster. Absorbed singletons are left empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph` - The graph being clustered.
+    /// * `floor` - The similarity the best neighbor must exceed.
+    fn absorb_singletons<G: Graph>(&mut self, graph: &G, floor: Similarity) {
+        let mut positions = HashMap::new();
+        for (position, cluster) in self.clusters_so_far.iter().enumerate() {
+            for index in cluster {
+                positions.insert(*index, position);
+            }
+        }
+
+        let mut singletons =
+            self.clusters_so_far.iter().enumerate()
+                .filter(|(_, cluster)| cluster.len() == 1)
+                .map(|(position, cluster)| (cluster[0], position))
+                .filter(|(index, _)| !self.config.stop_indices.contains(index))
+                .collect::<Vec<(Index, usize)>>();
+        singletons.sort_unstable();
+
+        for (index, position) in singletons {
+            let best_neighbor =
+                graph.neighbors(index).into_iter()
+                    .min_by(|(index_1, similarity_1), (index_2, similarity_2)| {
+                        similarity_2.partial_cmp(similarity_1).unwrap().then(index_1.cmp(index_2))
+                    });
+
+            if let Some((neighbor, similarity)) = best_neighbor {
+                let target = positions[&neighbor];
+                if similarity > floor &&
+                    self.clusters_so_far[target].len() > 1 &&
+                    self.can_join(index, &self.clusters_so_far[target]) {
+                    self.clusters_so_far[position].clear();
+                    self.clusters_so_far[target].push(index);
+                }
+            }
+        }
+    }
+
+    /// Ascertain whether an element can join a cluster without violating cannot-link constraints.
+    fn can_join(&self, index: Index, cluster: &[Index]) -> bool {
+        match self.cannot_link.get(&index) {
+            None => true,
+            Some(excluded) => !cluster.iter().any(|member| excluded.contains(member)),
+        }
+    }
+
+    /// Ascertain whether the current cluster must be recursively split. Nothing is split when
+    /// splitting is disabled. Clusters with fewer than three elements are never split, nor is a
+    /// cluster spanning the entire matrix (as splitting it would recurse into an identical
+    /// matrix).
+    ///
+    /// # Arguments
+    ///
+    /// * `matrix_size` - The size of the matrix being clustered.
+    fn needs_split(&self, matrix_size: Size) -> bool {
+        if !self.config.split {
+            return false;
+        }
+
+        let cluster_len = self.current_cluster_len();
+        let triggered = match self.config.split_trigger {
+            SplitTrigger::Absolute(min_len) => cluster_len >= min_len,
+            SplitTrigger::Relative(fraction) => cluster_len as f64 > fraction * matrix_size as f64,
+        };
+        triggered && cluster_len >= 3 && cluster_len < matrix_size
+    }
+
+    fn breaks_ties(&self) -> bool {
+        self.config.tie_break != TieBreak::FirstSeed
+    }
+
+    /// Record the similarity with which the current seed's siblings were claimed and move into
+    /// the current cluster any previously claimed sibling that ties and is favored by the policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The current cluster's seed.
+    /// * `row` - The seed's similarity matrix row.
+    fn resolve_ties(&mut self, seed: Index, row: &Row) {
+        let current_len = self.current_cluster_len();
+
+        for score in &row.scores {
+            let sibling = score.sibling_index;
+
+            match self.assigned_to.get(&sibling) {
+                None => {
+                    if sibling != seed && self.visited_so_far.contains(&sibling) {
+                        self.claimed_with.insert(sibling, score.similarity);
+                    }
+                }
+                Some(&position) => {
+                    let ties = self.claimed_with.get(&sibling) == Some(&score.similarity);
+                    let other_len = self.clusters_so_far[position].len();
+                    let favored = match self.config.tie_break {
+                        TieBreak::FirstSeed => false,
+                        TieBreak::PreferLarger => current_len > other_len,
+                        TieBreak::PreferSmaller => current_len < other_len,
+                    };
+
+                    if ties && favored && self.can_join(sibling, &self.current_cluster) {
+                        self.clusters_so_far[position].retain(|index| *index != sibling);
+                        self.assigned_to.remove(&sibling);
+                        self.current_cluster.push(sibling);
+                    }
+                }
+            }
+        }
+    }
+
+    fn can_add(&self, index: Index) -> bool {
+        !self.visited_so_far.contains(&index)
+    }
+
+    fn new_cluster(&mut self, index: Index) {
+        self.current_cluster = vec![];
+        self.add_to_cluster(index);
+    }
+
+    /// Add an element to the current cluster unless it cannot link with any of its members, in
+    /// which case it remains unvisited. Elements already visited are never added again, even if
+    /// rows edited by hand hold duplicate scores for a sibling.
+    fn add_to_cluster(&mut self, index: Index) {
+        if !self.visited_so_far.contains(&index) && self.can_join(index, &self.current_cluster) {
+            self.current_cluster.push(index);
+            self.visited_so_far.insert(index);
+        }
+    }
+
+    fn current_cluster_len(&self) -> Size {
+        self.current_cluster.len()
+    }
+
+    fn commit_current_cluster(&mut self) {
+        if self.breaks_ties() {
+            let position = self.clusters_so_far.len();
+            for index in &self.current_cluster {
+                self.assigned_to.insert(*index, position);
+            }
+        }
+
+        self.clusters_so_far.push(mem::take(&mut self.current_cluster));
+    }
+
+    /// Commit as singletons the members of the current cluster weighing less than a minimum
+    /// within it, leaving the remaining members as the current cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - The current cluster's sub-matrix.
+    /// * `min_node_weight` - The minimum weight for a member to be retained.
+    ///
+    /// # Return
+    ///
+    /// The retained members' sub-matrix and the stripped members.
+    fn strip_weak_members(
+        &mut self,
+        similarity_matrix: SimilarityMatrix,
+        min_node_weight: Similarity,
+    ) -> (SimilarityMatrix, Vec<Index>) {
+        let positions = (0..similarity_matrix.size()).collect::<Vec<Index>>();
+        let (retained_matrix, retained) =
+            similarity_matrix.spin_off_filtered(&positions, 0.0, Some(min_node_weight));
+        if retained.len() == positions.len() {
+            return (similarity_matrix, vec![]);
+        }
+
+        let retained_set = retained.iter().copied().collect::<HashSet<Index>>();
+        let stripped =
+            positions.into_iter()
+                .filter(|position| !retained_set.contains(position))
+                .collect::<Vec<Index>>();
+
+        let stripped_members =
+            stripped.iter()
+                .map(|position| self.current_cluster[*position])
+                .collect::<Vec<Index>>();
+        self.commit_inner_clusters(stripped.into_iter().map(|position| vec![position]).collect());
+        self.current_cluster =
+            retained.into_iter()
+                .map(|position| self.current_cluster[position])
+                .collect::<Vec<Index>>();
+
+        (retained_matrix, stripped_members)
+    }
+
+    fn to_be_excluded(&self) -> &HashSet<Index> {
+        &self.visited_so_far
+    }
+
+    fn commit_inner_clusters(&mut self, sub_clusters: Vec<Cluster>) {
+        for sub_cluster in sub_clusters {
+            let cluster =
+                sub_cluster.iter()
+                    .map(|inner_index| self.current_cluster[*inner_index])
+                    .collect::<Vec<Index>>();
+
+            if self.breaks_ties() {
+                let position = self.clusters_so_far.len();
+                for index in &cluster {
+                    self.assigned_to.insert(*index, position);
+                }
+            }
+
+            self.clusters_so_far.push(cluster);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use strsim::normalized_damerau_levenshtein;
+
+    use crate::index_pair::cartesian::CartesianIndexPairIterator;
+    use crate::utils::*;
+
+    use super::*;
+
+    pub(crate) fn matrix_from(size: Size, edges: &[(Index, Index, Similarity)]) -> SimilarityMatrix {
+        SimilarityMatrix::from_triplets(size, 0.0, edges.to_vec())
+    }
+
+    #[test]
+    fn creates_simple_clusters() {
+        let names = &string_vec(vec![
+            "alejandro", "alejo",
+            "martha", "marta",
+            "marlene", "marleny", "malrene",
+            "ricardo"
+        ]);
+
+        let expected_clusters: Vec<Cluster> = vec![
+            vec![0, 1, ], // "alejandro", "alejo",
+            vec![2, 3, ], // "martha", "marta",
+            vec![4, 5, 6, ], // "marlene", "marleny", "malrene",
+            vec![7, ], // "ricardo"
+        ];
+
+        let similarity_matrix = SimilarityMatrix::new(
+            names,
+            0.45,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1, t2| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        let mut clustering = Clusterer::cluster(similarity_matrix);
+
+        clustering.clusters.sort_by(|cluster1, cluster2| cluster1[0].cmp(&cluster2[0]));
+
+        assert_eq!(clustering.clusters, expected_clusters);
+
+        let cluster_lens = clustering.iter_clusters().map(|cluster| cluster.len()).collect::<Vec<Size>>();
+        assert_eq!(cluster_lens, vec![2, 2, 3, 1]);
+
+        let (clusters, singletons) = clustering.partition();
+        assert_eq!(clusters, vec![vec![0, 1], vec![2, 3], vec![4, 5, 6]]);
+        assert_eq!(singletons, vec![7]);
+    }
+
+    #[test]
+    fn finds_medoids() {
+        let names = string_vec(vec!["alejandra", "alexandro", "alejandro", "marlene"]);
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            0.0,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2),
+        );
+        let clustering = ClusteringResult::new(vec![vec![0, 1, 2], vec![3]], vec![], similarity_matrix);
+
+        assert_eq!(clustering.medoids(), vec![2, 3]);
+    }
+
+    #[test]
+    fn computes_cluster_internal_weight() {
+        let edges = vec![(0, 1, 0.5), (0, 2, 0.25), (1, 2, 0.125), (2, 3, 0.75)];
+        let clustering = ClusteringResult::new(vec![vec![2, 0, 1], vec![3]], vec![], matrix_from(4, &edges));
+
+        assert_eq!(clustering.cluster_internal_weight(), vec![0.875, 0.0]);
+    }
+
+    #[test]
+    fn clusters_single_element() {
+        let clustering = Clusterer::cluster(matrix_from(1, &[]));
+
+        assert_eq!(clustering.clusters, vec![vec![0]]);
+    }
+
+    #[test]
+    fn clusters_two_similar_elements() {
+        let clustering = Clusterer::cluster(matrix_from(2, &[(0, 1, 0.9)]));
+
+        assert_eq!(clustering.clusters, vec![vec![0, 1]]);
+    }
+
+    #[test]
+    fn honors_cannot_link() {
+        let edges = vec![(0, 1, 0.9), (0, 2, 0.8), (3, 4, 0.9)];
+        let config = ClustererConfig {
+            cannot_link: vec![(2, 0)],
+            ..ClustererConfig::default()
+        };
+
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+
+        assert_eq!(clustering.clusters, vec![vec![0, 1], vec![3, 4], vec![2]]);
+    }
+
+    #[test]
+    fn honors_must_link() {
+        let edges = vec![(0, 1, 0.9), (3, 4, 0.9)];
+        let config = ClustererConfig {
+            must_link: vec![(1, 4), (0, 2)],
+            ..ClustererConfig::default()
+        };
+
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+
+        assert_eq!(clustering.clusters, vec![vec![0, 1, 3, 4, 2]]);
+    }
+
+    #[test]
+    fn prefers_cannot_link_over_must_link() {
+        let edges = vec![(0, 1, 0.9), (3, 4, 0.9)];
+        let config = ClustererConfig {
+            must_link: vec![(1, 4), (0, 2)],
+            cannot_link: vec![(2, 3)],
+            ..ClustererConfig::default()
+        };
+
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+
+        assert_eq!(clustering.clusters, vec![vec![0, 1, 3, 4], vec![2]]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_out_of_range_must_link() {
+        let config = ClustererConfig { must_link: vec![(0, 5)], ..ClustererConfig::default() };
+
+        Clusterer::cluster_with(matrix_from(5, &[(0, 1, 0.9)]), &config);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_out_of_range_cannot_link() {
+        let config = ClustererConfig { cannot_link: vec![(7, 1)], ..ClustererConfig::default() };
+
+        Clusterer::cluster_with(matrix_from(5, &[(0, 1, 0.9)]), &config);
+    }
+
+    #[test]
+    fn caps_cluster_size() {
+        let edges = vec![
+            (0, 1, 0.9), (0, 2, 0.85), (0, 3, 0.8), (1, 2, 0.7), (1, 3, 0.65), (2, 3, 0.6),
+            (3, 4, 0.55),
+        ];
+        assert_eq!(Clusterer::cluster(matrix_from(5, &edges)).clusters.len(), 1);
+
+        for max_cluster_size in 1..5 {
+            let config = ClustererConfig {
+                max_cluster_size: Some(max_cluster_size),
+                ..ClustererConfig::default()
+            };
+
+            let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+
+            assert!(clustering.clusters.iter().all(|cluster| cluster.len() <= max_cluster_size));
+            assert_eq!(clustering.validate(5), Ok(()));
+        }
+
+        let config = ClustererConfig { max_cluster_size: Some(0), ..ClustererConfig::default() };
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+        assert_eq!(clustering.clusters.len(), 5);
+    }
+
+    #[test]
+    fn seeds_from_anchors() {
+        let edges = vec![(0, 1, 0.9), (1, 2, 0.8), (3, 4, 0.7)];
+
+        let clustering = Clusterer::cluster(matrix_from(5, &edges));
+        assert_eq!(clustering.clusters[0], vec![1, 0, 2]);
+
+        let config = ClustererConfig::default();
+        let clustering = Clusterer::cluster_with_anchors(matrix_from(5, &edges), &config, &[4, 2, 1]);
+        assert_eq!(clustering.clusters, vec![vec![4, 3], vec![2, 1], vec![0]]);
+
+        let config = ClustererConfig { cannot_link: vec![(1, 2)], ..ClustererConfig::default() };
+        let clustering = Clusterer::cluster_with_anchors(matrix_from(5, &edges), &config, &[4, 2, 1]);
+        assert_eq!(clustering.clusters, vec![vec![4, 3], vec![2], vec![1, 0]]);
+    }
+
+    #[test]
+    fn keeps_stop_indices_as_singletons() {
+        // 2 is the most similar element of all, yet stop-listed.
+        let edges = vec![(0, 1, 0.8), (0, 2, 0.95), (1, 2, 0.9), (3, 4, 0.7)];
+        let config = ClustererConfig {
+            stop_indices: vec![2].into_iter().collect(),
+            must_link: vec![(2, 3)],
+            ..ClustererConfig::default()
+        };
+
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+
+        assert_eq!(clustering.clusters, vec![vec![0, 1], vec![3, 4], vec![2]]);
+    }
+
+    #[test]
+    fn iterates_and_collects_clusters() {
+        let clustering = ClusteringResult::new(vec![vec![2, 0], vec![1], vec![3]], vec![], matrix_from(4, &[]));
+        assert_eq!((&clustering).into_iter().count(), 3);
+
+        let non_singletons = clustering.into_iter().filter(|cluster| cluster.len() > 1).collect::<Clustering>();
+        assert_eq!(non_singletons, Clustering(vec![vec![2, 0]]));
+        assert_eq!(non_singletons[0], vec![2, 0]);
+        assert_eq!((&non_singletons).into_iter().count(), 1);
+        assert_eq!(non_singletons.into_inner(), vec![vec![2, 0]]);
+    }
+
+    #[test]
+    fn finds_shared_members() {
+        let partition = ClusteringResult::new(vec![vec![2, 0], vec![1, 3]], vec![], matrix_from(4, &[]));
+        assert!(partition.shared_members().is_empty());
+
+        let overlapping = ClusteringResult::new(vec![vec![2, 0], vec![1, 2], vec![2, 3, 0]], vec![], matrix_from(4, &[]));
+        let shared_members = overlapping.shared_members();
+        assert_eq!(shared_members.len(), 2);
+        assert_eq!(shared_members[&2], vec![0, 1, 2]);
+        assert_eq!(shared_members[&0], vec![0, 2]);
+    }
+
+    #[test]
+    fn diffs_clusterings() {
+        let before = ClusteringResult::new(vec![vec![0, 1, 2], vec![3], vec![4]], vec![], matrix_from(5, &[]));
+        let after = ClusteringResult::new(vec![vec![1, 0], vec![2, 3], vec![4]], vec![], matrix_from(5, &[]));
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.split_pairs, vec![(0, 2), (1, 2)]);
+        assert_eq!(diff.merged_pairs, vec![(2, 3)]);
+        assert_eq!(diff.moved, vec![0, 1, 2, 3]);
+        assert_eq!(diff.to_string(), "2 pairs split, 1 pairs merged, 4 elements moved");
+
+        assert!(before.diff(&before).is_empty());
+    }
+
+    #[test]
+    fn applies_neighbor_thresholds() {
+        let edges = [(0, 1, 0.9), (0, 2, 0.6), (3, 4, 0.6)];
+        assert_eq!(
+            Clusterer::new(&Default::default()).run(&matrix_from(5, &edges)),
+            vec![vec![0, 1, 2], vec![3, 4]],
+        );
+
+        let config = ClustererConfig {
+            neighbor_thresholds: Some(vec![0.8, 0.0, 0.0, 0.8, 0.8]),
+            ..Default::default()
+        };
+        assert_eq!(config.neighbor_threshold(0), 0.8);
+        assert_eq!(config.neighbor_threshold(7), 0.0);
+        assert_eq!(
+            Clusterer::new(&config).run(&matrix_from(5, &edges)),
+            vec![vec![0, 1], vec![2], vec![3], vec![4]],
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_mismatched_neighbor_thresholds() {
+        let config = ClustererConfig { neighbor_thresholds: Some(vec![0.8, 0.8]), ..Default::default() };
+
+        Clusterer::new(&config).run(&matrix_from(5, &[(0, 1, 0.9)]));
+    }
+
+    #[test]
+    fn absorbs_singletons_above_floor() {
+        // 3 is left alone because its only neighbor, 2, is claimed by 1's cluster first.
+        let edges = [(0, 1, 0.9), (1, 2, 0.9), (2, 3, 0.6)];
+        assert_eq!(
+            Clusterer::new(&Default::default()).run(&matrix_from(4, &edges)),
+            vec![vec![1, 0, 2], vec![3]],
+        );
+
+        let absorbing = |floor| ClustererConfig { absorb_singletons_above: Some(floor), ..Default::default() };
+        assert_eq!(
+            Clusterer::new(&absorbing(0.5)).run(&matrix_from(4, &edges)),
+            vec![vec![1, 0, 2, 3]],
+        );
+        assert_eq!(
+            Clusterer::new(&absorbing(0.9)).run(&matrix_from(4, &edges)),
+            vec![vec![1, 0, 2], vec![3]],
+        );
+
+        let stopping = ClustererConfig { stop_indices: vec![1].into_iter().collect(), ..absorbing(0.5) };
+        let clustering = Clusterer::cluster_with(matrix_from(2, &[(0, 1, 0.9)]), &stopping);
+        assert_eq!(clustering.clusters, vec![vec![0], vec![1]]);
+        assert_eq!(clustering.validate(2), Ok(()));
+
+        // 0's best neighbor, 1, is itself a singleton and joins 3's cluster without dragging 0.
+        let chained = ClustererConfig {
+            split: false,
+            neighbor_thresholds: Some(vec![0.0, 0.85, 0.0, 0.85, 0.0]),
+            ..absorbing(0.5)
+        };
+        let edges = [(2, 3, 0.95), (3, 4, 0.9), (2, 4, 0.9), (1, 3, 0.8), (0, 1, 0.7)];
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &chained);
+        assert_eq!(clustering.clusters, vec![vec![3, 2, 4, 1], vec![0]]);
+        assert_eq!(clustering.validate(5), Ok(()));
+
+        // 0's best neighbor, 1, is a singleton, so 0 isn't attached to 2's weaker cluster instead.
+        let lonely = ClustererConfig {
+            split: false,
+            neighbor_thresholds: Some(vec![0.95, 0.95, 0.9, 0.9]),
+            ..absorbing(0.5)
+        };
+        let edges = [(2, 3, 0.95), (0, 1, 0.9), (0, 2, 0.7)];
+        let clustering = Clusterer::cluster_with(matrix_from(4, &edges), &lonely);
+        assert_eq!(clustering.clusters, vec![vec![2, 3], vec![0], vec![1]]);
+        assert_eq!(clustering.validate(4), Ok(()));
+    }
+
+    #[test]
+    fn clusters_subset_with_global_indices() {
+        let similarity_matrix = matrix_from(6, &[(0, 1, 0.9), (1, 3, 0.8), (3, 5, 0.9), (2, 4, 0.7)]);
+
+        let clustering = Clusterer::cluster_subset(&similarity_matrix, &[5, 3, 4, 2]);
+
+        assert_eq!(clustering.clusters, vec![vec![5, 3], vec![4, 2]]);
+        assert_eq!(clustering.similarity_matrix.size(), 6);
+        assert_eq!(clustering.similarity_matrix[3][5], 0.9);
+        assert_eq!(clustering.similarity_matrix[1][3], 0.0);
+        assert_eq!(clustering.cluster_of(2), Some(1));
+        assert_eq!(clustering.cluster_of(0), None);
+        assert_eq!(clustering.noise, vec![0, 1]);
+        assert_eq!(clustering.validate(6), Ok(()));
+    }
+
+    #[test]
+    fn refines_by_merging_clusters() {
+        use crate::evaluation::MassEvaluator;
+
+        let singletons = || ClusteringResult::new(
+            vec![vec![0], vec![1], vec![2], vec![3]],
+            vec![],
+            matrix_from(4, &[(0, 1, 0.9), (2, 3, 0.8), (1, 2, 0.1)]),
+        );
+        // A negative resolution rewards clusters, so only strong links are worth merging.
+        let evaluator = MassEvaluator { resolution: -0.2 };
+
+        let mut clustering = singletons();
+        let mut evaluations = vec![evaluator.evaluate(&clustering)];
+        while clustering.refine(1, 0.0, &evaluator) == 1 {
+            evaluations.push(evaluator.evaluate(&clustering));
+        }
+        assert_eq!(clustering.clusters, vec![vec![0, 1], vec![2, 3]]);
+        assert_eq!(clustering.cluster_of(3), Some(1));
+        assert_eq!(evaluations.len(), 3);
+        assert!(evaluations.windows(2).all(|pair| pair[1] > pair[0]));
+
+        let mut clustering = singletons();
+        assert_eq!(clustering.refine(10, 0.0, &evaluator), 2);
+        assert_eq!(clustering.clusters, vec![vec![0, 1], vec![2, 3]]);
+
+        let mut clustering = singletons();
+        assert_eq!(clustering.refine(10, 1.0, &evaluator), 0);
+        assert_eq!(clustering.clusters.len(), 4);
+    }
+
+    #[test]
+    fn clusters_from_warm_start() {
+        // 4 is closest to existing member 1, 5 is closer to 6 than to existing member 3.
+        let edges = [(0, 1, 0.9), (2, 3, 0.9), (1, 4, 0.8), (3, 5, 0.6), (5, 6, 0.9), (0, 2, 0.7)];
+
+        let clustering = Clusterer::cluster_warm(matrix_from(8, &edges), &[vec![2, 3], vec![1, 0]]);
+
+        assert_eq!(clustering.clusters, vec![vec![2, 3], vec![1, 0, 4], vec![5, 6], vec![7]]);
+
+        let clustering = Clusterer::cluster_warm(matrix_from(8, &edges), &[]);
+        assert_eq!(clustering.clusters, Clusterer::cluster(matrix_from(8, &edges)).clusters);
+    }
+
+    #[test]
+    fn displays_clusters() {
+        let names = string_vec(vec!["ana", "anna", "hanna"]);
+
+        assert_eq!(ClusterDisplay::new(&[2, 0, 1], &names).to_string(), "3,hanna,ana,anna");
+        assert_eq!(ClusterDisplay::new(&[1], &names).separator(" | ").to_string(), "1 | anna");
+        assert_eq!(ClusterDisplay::new(&[0, 1], &names).with_count(false).to_string(), "ana,anna");
+        assert_eq!(ClusterDisplay::new(&[], &names).with_count(false).to_string(), "");
+    }
+
+    #[test]
+    fn writes_label_csv() {
+        let clustering = ClusteringResult::new(vec![vec![2, 0], vec![3]], vec![1], matrix_from(4, &[]));
+        let names = string_vec(vec!["a", "b,c", "d\"e", "f"]);
+
+        let mut out = Vec::new();
+        clustering.write_label_csv(&mut out, true, Some(&names)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "element,label,name\n0,0,a\n1,-1,\"b,c\"\n2,0,\"d\"\"e\"\n3,1,f\n",
+        );
+
+        let mut out = Vec::new();
+        clustering.write_label_csv(&mut out, false, None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "0,0\n1,-1\n2,0\n3,1\n");
+    }
+
+    #[test]
+    fn stops_at_deadline() {
+        let edges = vec![(0, 1, 0.9), (1, 2, 0.8), (3, 4, 0.7)];
+
+        let later = Instant::now() + Duration::from_secs(60);
+
+        let (clustering, finished) =
+            Clusterer::cluster_with_deadline(matrix_from(5, &edges), &ClustererConfig::default(), later);
+        assert!(finished);
+        assert_eq!(clustering.clusters, Clusterer::cluster(matrix_from(5, &edges)).clusters);
+
+        let capped = ClustererConfig { max_cluster_size: Some(2), ..ClustererConfig::default() };
+        let (clustering, finished) = Clusterer::cluster_with_deadline(matrix_from(5, &edges), &capped, later);
+        assert!(finished);
+        assert_eq!(clustering.clusters, Clusterer::cluster_with(matrix_from(5, &edges), &capped).clusters);
+        assert!(clustering.clusters.iter().all(|cluster| cluster.len() <= 2));
+
+        let (clustering, finished) =
+            Clusterer::cluster_with_deadline(matrix_from(5, &edges), &ClustererConfig::default(), Instant::now());
+        assert!(!finished);
+        assert_eq!(clustering.clusters, vec![vec![0], vec![1], vec![2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn keeps_partition_across_recursion() {
+        // Rows holding duplicate scores, as rows edited by hand may, would place siblings twice
+        // in a cluster, from where the recursive split would leak them into a second cluster.
+        let edges = vec![(0, 1, 0.9), (0, 2, 0.8), (1, 2, 0.5), (2, 3, 0.7), (3, 4, 0.6), (4, 5, 0.95)];
+        let duplicated_matrix = || {
+            let mut similarity_matrix = matrix_from(7, &edges);
+            for row in similarity_matrix.rows.iter_mut() {
+                let duplicates = row.scores.clone();
+                row.scores.extend(duplicates);
+            }
+            similarity_matrix.sort_rows();
+            similarity_matrix
+        };
+        let degrees = duplicated_matrix().rows.iter().map(|row| row.scores.len()).collect::<Vec<Size>>();
+        assert_eq!(degrees, vec![4, 4, 6, 4, 4, 2, 0]);
+
+        for tie_break in &[TieBreak::FirstSeed, TieBreak::PreferLarger, TieBreak::PreferSmaller] {
+            let config = ClustererConfig { tie_break: *tie_break, ..ClustererConfig::default() };
+            let mut events = Vec::new();
+            let clustering =
+                Clusterer::cluster_traced(duplicated_matrix(), &config, &mut |event| events.push(event));
+
+            assert!(events.iter().any(|event| matches!(event, TraceEvent::RecursingInto { .. })));
+            assert_eq!(clustering.validate(7), Ok(()));
+        }
+    }
+
+    #[test]
+    fn clusters_dropping_matrix() {
+        let edges = vec![(0, 1, 0.9), (2, 3, 0.8)];
+
+        assert_eq!(
+            Clusterer::cluster_drop_matrix(matrix_from(5, &edges)),
+            Clusterer::cluster(matrix_from(5, &edges)).clusters);
+    }
+
+    #[test]
+    fn reuses_clusterer() {
+        let mut clusterer = Clusterer::new(&ClustererConfig::default());
+
+        assert_eq!(clusterer.run(&matrix_from(3, &[(0, 2, 0.9)])), vec![vec![0, 2], vec![1]]);
+        assert_eq!(clusterer.run(&matrix_from(2, &[(0, 1, 0.9)])), vec![vec![0, 1]]);
+        assert_eq!(clusterer.run(&matrix_from(1, &[])), vec![vec![0]]);
+    }
+
+    #[test]
+    fn never_splits_tiny_clusters() {
+        let mut clusterer = Clusterer::new(&ClustererConfig::default());
+
+        clusterer.new_cluster(0);
+        assert!(!clusterer.needs_split(1));
+
+        clusterer.add_to_cluster(1);
+        assert!(!clusterer.needs_split(2));
+        assert!(!clusterer.needs_split(100));
+
+        clusterer.add_to_cluster(2);
+        assert!(!clusterer.needs_split(3));
+        assert!(clusterer.needs_split(4));
+    }
+
+    #[test]
+    fn triggers_split_relative_to_matrix_size() {
+        let config = ClustererConfig {
+            split_trigger: SplitTrigger::Relative(0.1),
+            ..ClustererConfig::default()
+        };
+        let mut clusterer = Clusterer::new(&config);
+
+        clusterer.new_cluster(0);
+        clusterer.add_to_cluster(1);
+        clusterer.add_to_cluster(2);
+        assert!(clusterer.needs_split(20));
+        assert!(!clusterer.needs_split(30));
+        assert!(!clusterer.needs_split(3));
+
+        let config = ClustererConfig {
+            split_trigger: SplitTrigger::Absolute(4),
+            ..ClustererConfig::default()
+        };
+        let mut clusterer = Clusterer::new(&config);
+
+        clusterer.new_cluster(0);
+        clusterer.add_to_cluster(1);
+        clusterer.add_to_cluster(2);
+        assert!(!clusterer.needs_split(10));
+        clusterer.add_to_cluster(3);
+        assert!(clusterer.needs_split(10));
+    }
+
+    #[test]
+    fn carries_seed_into_inner_config() {
+        let config = ClustererConfig {
+            seed: Some(42),
+            must_link: vec![(0, 1)],
+            ..ClustererConfig::default()
+        };
+
+        let inner_config = config.inner_config();
+        assert_eq!(inner_config.seed, Some(42));
+        assert!(inner_config.must_link.is_empty());
+        assert_eq!(ClustererConfig::default().seed, None);
+    }
+
+    #[test]
+    fn splits_according_to_strategy() {
+        // Seed 0 pulls in all but 6. The weakest edge is 0-3, and 4 is more similar to 0 than to 3
+        // but only linked to 3 through 5.
+        let edges = vec![
+            (0, 1, 0.5), (0, 2, 0.55), (0, 3, 0.2), (0, 4, 0.3), (0, 5, 0.25),
+            (1, 2, 0.9), (3, 5, 0.9), (4, 5, 0.85),
+        ];
+
+        let cluster_with = |split_strategy| {
+            let config = ClustererConfig { split_strategy, ..ClustererConfig::default() };
+            let mut clusters = Clusterer::cluster_with(matrix_from(7, &edges), &config).clusters;
+            for cluster in clusters.iter_mut() {
+                cluster.sort();
+            }
+            clusters.sort();
+            clusters
+        };
+
+        assert_eq!(cluster_with(SplitStrategy::WeakestEdgeBisect), vec![vec![0, 1, 2, 4], vec![3, 5], vec![6]]);
+        assert_eq!(cluster_with(SplitStrategy::SingleLinkageComponents), vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]]);
+    }
+
+    #[test]
+    fn strips_weak_members_before_splitting() {
+        // 3 hangs onto the 0-1-2 triangle by a single weak edge.
+        let edges = vec![(0, 1, 0.9), (0, 2, 0.9), (1, 2, 0.9), (0, 3, 0.3)];
+
+        let clustering = Clusterer::cluster(matrix_from(5, &edges));
+        assert_eq!(clustering.clusters, vec![vec![0, 1, 2, 3], vec![4]]);
+
+        let config = ClustererConfig { min_node_weight: Some(0.5), ..ClustererConfig::default() };
+        let mut events = Vec::new();
+        let clustering =
+            Clusterer::cluster_traced(matrix_from(5, &edges), &config, &mut |event| events.push(event));
+        assert_eq!(clustering.clusters, vec![vec![3], vec![0, 1, 2], vec![4]]);
+        assert!(events.contains(&TraceEvent::ClusterCommitted { members: vec![3] }));
+
+        let config = ClustererConfig { min_node_weight: Some(2.0), ..ClustererConfig::default() };
+        let clustering = Clusterer::cluster_with(matrix_from(5, &edges), &config);
+        assert_eq!(clustering.clusters, vec![vec![1], vec![2], vec![3], vec![0], vec![4]]);
+    }
+
+    #[test]
+    fn skips_splitting_when_disabled() {
+        // Seed 0 pulls in all but 6, which splitting would break apart.
+        let edges = vec![
+            (0, 1, 0.5), (0, 2, 0.55), (0, 3, 0.2), (0, 4, 0.3), (0, 5, 0.25),
+            (1, 2, 0.9), (3, 5, 0.9), (4, 5, 0.85),
+        ];
+        let config = ClustererConfig {
+            split_strategy: SplitStrategy::WeakestEdgeBisect,
+            ..ClustererConfig::default()
+        };
+
+        let clustering = Clusterer::cluster_with(matrix_from(7, &edges), &config);
+        assert_eq!(clustering.clusters.len(), 3);
+
+        let config = ClustererConfig { split: false, ..config };
+        let clustering = Clusterer::cluster_with(matrix_from(7, &edges), &config);
+        assert_eq!(clustering.clusters, vec![vec![0, 2, 1, 4, 5, 3], vec![6]]);
+    }
+
+    #[test]
+    fn traces_clustering_decisions() {
+        // 5 is similar to 1-4, while 1-2 and 3-4 are only similar within each pair.
+        let edges = vec![
+            (5, 1, 0.6), (5, 2, 0.55), (5, 3, 0.5), (5, 4, 0.45),
+            (1, 2, 0.9), (3, 4, 0.9),
+        ];
+
+        let mut events = Vec::new();
+        let clustering = Clusterer::cluster_traced(
+            matrix_from(6, &edges),
+            &ClustererConfig::default(),
+            &mut |event| events.push(event),
+        );
+
+        assert_eq!(events, vec![
+            TraceEvent::SeedPicked { index: 5 },
+            TraceEvent::SiblingAdded { index: 1 },
+            TraceEvent::SiblingAdded { index: 2 },
+            TraceEvent::SiblingAdded { index: 3 },
+            TraceEvent::SiblingAdded { index: 4 },
+            TraceEvent::RecursingInto { size: 5 },
+            TraceEvent::SeedPicked { index: 5 },
+            TraceEvent::SiblingAdded { index: 1 },
+            TraceEvent::SiblingAdded { index: 2 },
+            TraceEvent::SiblingAdded { index: 3 },
+            TraceEvent::SiblingAdded { index: 4 },
+            TraceEvent::ClusterCommitted { members: vec![5, 1, 2, 3, 4] },
+            TraceEvent::SeedPicked { index: 0 },
+            TraceEvent::ClusterCommitted { members: vec![0] },
+        ]);
+        assert_eq!(clustering.clusters, vec![vec![5, 1, 2, 3, 4], vec![0]]);
+    }
+
+    #[test]
+    fn breaks_ties_according_to_policy() {
+        // A=0, B=1, C=2, D=3, E=4, X=5. X is equally similar to A and D.
+        let edges = vec![
+            (0, 1, 0.9), (0, 2, 0.9), (0, 5, 0.8),
+            (3, 4, 0.9), (3, 5, 0.8),
+        ];
+
+        let cluster_with = |tie_break: TieBreak| {
+            let config = ClustererConfig { tie_break, ..ClustererConfig::default() };
+            let mut clustering = Clusterer::cluster_with(matrix_from(6, &edges), &config);
+            for cluster in clustering.clusters.iter_mut() {
+                cluster.sort();
+            }
+            clustering.clusters.sort();
+            clustering.clusters
+        };
+
+        assert_eq!(cluster_with(TieBreak::FirstSeed), vec![vec![0, 1, 2, 5], vec![3, 4]]);
+        assert_eq!(cluster_with(TieBreak::PreferLarger), vec![vec![0, 1, 2, 5], vec![3, 4]]);
+        assert_eq!(cluster_with(TieBreak::PreferSmaller), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+    }
+
+    #[test]
+    fn validates_partition() {
+        let clustering = |clusters: Vec<Cluster>, noise: Vec<Index>|
+            ClusteringResult::new(clusters, noise, matrix_from(4, &[]));
+
+        assert_eq!(clustering(vec![vec![0, 2], vec![3, 1]], vec![]).validate(4), Ok(()));
+        assert_eq!(clustering(vec![vec![0, 2]], vec![3, 1]).validate(4), Ok(()));
+        assert_eq!(
+            clustering(vec![vec![0, 2], vec![2, 1, 3]], vec![]).validate(4),
+            Err(ClusterError::Duplicate { index: 2 }));
+        assert_eq!(
+            clustering(vec![vec![0, 2], vec![3]], vec![]).validate(4),
+            Err(ClusterError::Missing { index: 1 }));
+        assert_eq!(
+            clustering(vec![vec![0, 2], vec![3, 1, 4]], vec![]).validate(4),
+            Err(ClusterError::OutOfRange { index: 4, size: 4 }));
+    }
+
+    #[test]
+    fn creates_recursive_cluster() {
+        let min_similarity = 0.7;
+        let element_count = 100;
+        let expected_cluster_count = 54;
+
+        let names = read_file_lines(String::from("data/surnames.txt"), element_count);
+
+        let expected_element_count = names.len();
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            min_similarity,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1, t2| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        let clustering = Clusterer::cluster(similarity_matrix);
+
+        assert_eq!(clustering.clusters.len(), expected_cluster_count);
+        assert_eq!(clustering.validate(expected_element_count), Ok(()));
+
+        let actual_element_count = clustering.
+            clusters.iter()
+            .map(|cluster| cluster.len())
+            .sum::<usize>();
+        assert_eq!(actual_element_count, expected_element_count);
+
+        assert!(clustering.verify_connected(min_similarity).is_empty());
+    }
+
+    #[test]
+    fn finds_disconnected_clusters() {
+        let similarity_matrix = matrix_from(5, &[(0, 1, 0.9), (1, 2, 0.6), (3, 4, 0.2), (2, 3, 0.9)]);
+        let clustering = ClusteringResult::new(vec![vec![0, 1, 2], vec![3, 4], vec![0, 4]], vec![], similarity_matrix);
+
+        assert_eq!(clustering.verify_connected(0.0), vec![2]);
+        assert_eq!(clustering.verify_connected(0.5), vec![1, 2]);
+        assert_eq!(clustering.verify_connected(0.7), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn clusters_unsorted_matrix() {
+        let names = read_file_lines(String::from("data/surnames.txt"), 100);
+
+        let cluster_with = |sorted: bool| {
+            let mut pairs = CartesianIndexPairIterator::new(names.len());
+            let metric = |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str());
+            let similarity_matrix =
+                if sorted {
+                    SimilarityMatrix::new(&names, 0.7, &mut pairs, metric)
+                } else {
+                    SimilarityMatrix::new_unsorted(&names, 0.7, &mut pairs, metric)
+                };
+            Clusterer::cluster(similarity_matrix).clusters
+        };
+
+        let unsorted_clusters = cluster_with(false);
+        assert_eq!(unsorted_clusters, cluster_with(true));
+
+        let mut clustered_indices = unsorted_clusters.concat();
+        clustered_indices.sort();
+        assert_eq!(clustered_indices, (0..names.len()).collect::<Vec<Index>>());
+    }
+
+    #[test]
+    fn finds_cluster_of_index() {
+        let mut clustering = ClusteringResult::new(vec![vec![2, 0], vec![3]], vec![1], matrix_from(4, &[]));
+
+        assert_eq!(clustering.labels(), &[Some(0), None, Some(0), Some(1)]);
+        assert_eq!(clustering.cluster_of(0), Some(0));
+        assert_eq!(clustering.cluster_of(1), None);
+        assert_eq!(clustering.cluster_of(3), Some(1));
+        assert_eq!(clustering.cluster_of(4), None);
+
+        clustering.clusters.swap(0, 1);
+        clustering.reset_labels();
+        assert_eq!(clustering.cluster_of(0), Some(1));
+    }
+}
@@ -0,0 +1,166 @@
+//! This module contains single-linkage hierarchical clustering over a similarity matrix. Edges
+//! are union-found from highest to lowest similarity, which makes this method fast and fully
+//! deterministic.
+
+use crate::{Index, Size};
+use crate::sim_matrix::SimilarityMatrix;
+use crate::sim_metric::Similarity;
+
+use super::{Cluster, Clusterer};
+
+/// A single merge of two dendrogram nodes. Nodes `0..size` are the input elements; the node
+/// created by the `k`-th merge has id `size + k`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Merge {
+    /// The id of the first merged node.
+    pub left: usize,
+    /// The id of the second merged node.
+    pub right: usize,
+    /// The similarity at which both nodes were merged.
+    pub similarity: Similarity,
+    /// The number of elements in the resulting node.
+    pub size: Size,
+}
+
+/// A single-linkage dendrogram: the sequence of merges, in non-increasing similarity order.
+/// Elements never linked to any other remain unmerged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dendrogram {
+    /// The number of elements in the input set.
+    pub size: Size,
+    /// The merges, in the order in which they took place.
+    pub merges: Vec<Merge>,
+}
+
+/// Dendrogram implementation.
+impl Dendrogram {
+    /// Cut this dendrogram at a given similarity, keeping only merges at or above it.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity` - The minimum similarity of the merges to keep.
+    ///
+    /// # Return
+    ///
+    /// The resulting clusters, ordered by their smallest member, each with ascending members.
+    pub fn cut_at(&self, similarity: Similarity) -> Vec<Cluster> {
+        let mut members: Vec<Cluster> = (0..self.size).map(|index| vec![index]).collect();
+
+        for merge in self.merges.iter().take_while(|merge| merge.similarity >= similarity) {
+            let mut merged = std::mem::take(&mut members[merge.left]);
+            merged.append(&mut members[merge.right]);
+            members.push(merged);
+        }
+
+        let mut clusters =
+            members.into_iter()
+                .filter(|cluster| !cluster.is_empty())
+                .map(|mut cluster| {
+                    cluster.sort();
+                    cluster
+                })
+                .collect::<Vec<Cluster>>();
+        clusters.sort();
+        clusters
+    }
+}
+
+/// Single-linkage implementation.
+impl Clusterer {
+    /// Build the single-linkage dendrogram of a similarity matrix by processing its edges in
+    /// descending similarity order (ties broken by ascending index pair).
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    ///
+    /// # Return
+    ///
+    /// The resulting dendrogram.
+    pub fn single_linkage(similarity_matrix: &SimilarityMatrix) -> Dendrogram {
+        let size = similarity_matrix.size();
+
+        let edges = similarity_matrix.sorted_edges(true);
+
+        let mut parents = (0..size).collect::<Vec<Index>>();
+        let mut node_ids = (0..size).collect::<Vec<usize>>();
+        let mut node_sizes = vec![1; size];
+        let mut merges = Vec::new();
+
+        for (row, column, similarity) in edges {
+            let (root_1, root_2) = (find_root(&mut parents, row), find_root(&mut parents, column));
+            if root_1 == root_2 {
+                continue;
+            }
+
+            let merged_size = node_sizes[root_1] + node_sizes[root_2];
+            merges.push(Merge {
+                left: node_ids[root_1],
+                right: node_ids[root_2],
+                similarity,
+                size: merged_size,
+            });
+
+            parents[root_2] = root_1;
+            node_ids[root_1] = size + merges.len() - 1;
+            node_sizes[root_1] = merged_size;
+        }
+
+        Dendrogram { size, merges }
+    }
+}
+
+/// Find the root of an element's union-find tree, compressing the path along the way.
+fn find_root(parents: &mut [Index], index: Index) -> Index {
+    let mut root = index;
+    while parents[root] != root {
+        root = parents[root];
+    }
+
+    let mut current = index;
+    while parents[current] != root {
+        let next = parents[current];
+        parents[current] = root;
+        current = next;
+    }
+
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::matrix_from;
+
+    #[test]
+    fn builds_dendrogram() {
+        let edges = vec![
+            (0, 1, 0.9), (1, 2, 0.7), (0, 2, 0.6),
+            (3, 4, 0.8), (2, 3, 0.5),
+        ];
+
+        let dendrogram = Clusterer::single_linkage(&matrix_from(6, &edges));
+
+        assert_eq!(dendrogram.merges, vec![
+            Merge { left: 0, right: 1, similarity: 0.9, size: 2 },
+            Merge { left: 3, right: 4, similarity: 0.8, size: 2 },
+            Merge { left: 6, right: 2, similarity: 0.7, size: 3 },
+            Merge { left: 8, right: 7, similarity: 0.5, size: 5 },
+        ]);
+    }
+
+    #[test]
+    fn cuts_dendrogram() {
+        let edges = vec![
+            (0, 1, 0.9), (1, 2, 0.7), (0, 2, 0.6),
+            (3, 4, 0.8), (2, 3, 0.5),
+        ];
+
+        let dendrogram = Clusterer::single_linkage(&matrix_from(6, &edges));
+
+        assert_eq!(dendrogram.cut_at(1.0), vec![vec![0], vec![1], vec![2], vec![3], vec![4], vec![5]]);
+        assert_eq!(dendrogram.cut_at(0.8), vec![vec![0, 1], vec![2], vec![3, 4], vec![5]]);
+        assert_eq!(dendrogram.cut_at(0.6), vec![vec![0, 1, 2], vec![3, 4], vec![5]]);
+        assert_eq!(dendrogram.cut_at(0.0), vec![vec![0, 1, 2, 3, 4], vec![5]]);
+    }
+}
@@ -0,0 +1,251 @@
+//! This module contains threshold sweeps selecting the best clustering of a similarity matrix
+//! according to a `ClusterEvaluator`.
+
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::Index;
+use crate::evaluation::{ClusterEvaluation, ClusterEvaluator, pairwise_f1};
+use crate::sim_matrix::SimilarityMatrix;
+use crate::sim_metric::Similarity;
+
+use super::{Clusterer, ClusteringResult};
+
+/// Threshold sweep implementation.
+impl Clusterer {
+    /// Cluster a similarity matrix at each of a set of thresholds and keep the best clustering.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `thresholds` - The minimum similarities to cluster at. Must not be empty.
+    /// * `evaluator` - The evaluator ranking the resulting clusterings.
+    ///
+    /// # Return
+    ///
+    /// The best `Clustering` result.
+    pub fn best_clustering<E: ClusterEvaluator>(
+        similarity_matrix: &SimilarityMatrix,
+        thresholds: &[Similarity],
+        evaluator: &E,
+    ) -> ClusteringResult {
+        Clusterer::best_clustering_scored(similarity_matrix, thresholds, evaluator).0
+    }
+
+    /// Cluster a similarity matrix at each of a set of thresholds and keep the best clustering
+    /// along with the threshold producing it and its evaluation. Earlier thresholds win ties.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `thresholds` - The minimum similarities to cluster at. Must not be empty.
+    /// * `evaluator` - The evaluator ranking the resulting clusterings.
+    ///
+    /// # Return
+    ///
+    /// The best `Clustering` result, its threshold and its evaluation.
+    pub fn best_clustering_scored<E: ClusterEvaluator>(
+        similarity_matrix: &SimilarityMatrix,
+        thresholds: &[Similarity],
+        evaluator: &E,
+    ) -> (ClusteringResult, Similarity, ClusterEvaluation) {
+        assert!(!thresholds.is_empty(), "Cannot sweep an empty threshold set");
+
+        let indices = (0..similarity_matrix.size()).collect::<Vec<Index>>();
+
+        let mut best: Option<(ClusteringResult, Similarity, ClusterEvaluation)> = None;
+        for &threshold in thresholds {
+            let clustering = Clusterer::cluster(similarity_matrix.spin_off(&indices, threshold));
+            let evaluation = evaluator.evaluate(&clustering);
+
+            let improves = match &best {
+                None => true,
+                Some((_, _, best_evaluation)) => evaluator.best_of(evaluation, *best_evaluation),
+            };
+            if improves {
+                best = Some((clustering, threshold, evaluation));
+            }
+        }
+
+        best.unwrap()
+    }
+
+    /// Pick the threshold whose clustering best agrees with partial ground truth, as measured by
+    /// pairwise F1 over labeled pairs. Every distinct similarity value in the matrix is tried;
+    /// lower thresholds win ties.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `indices` - The elements to cluster.
+    /// * `truth` - Each matrix element's ground truth label, `None` for unlabeled elements.
+    ///
+    /// # Return
+    ///
+    /// The best threshold and its F1 score, or the matrix's minimum similarity and `0.0` if the
+    /// matrix holds no similarity values.
+    pub fn tune_threshold_f1(
+        similarity_matrix: &SimilarityMatrix,
+        indices: &[Index],
+        truth: &[Option<usize>],
+    ) -> (Similarity, f64) {
+        assert_eq!(truth.len(), similarity_matrix.size(), "Truth labels must cover the whole matrix");
+
+        let local_truth = indices.iter().map(|index| truth[*index]).collect::<Vec<Option<usize>>>();
+
+        let mut best = (similarity_matrix.min_similarity(), 0.0);
+        for &threshold in &similarity_matrix.similarity_values {
+            let clustering = Clusterer::cluster(similarity_matrix.spin_off(indices, threshold));
+            let f1 = pairwise_f1(&clustering.labels()[..indices.len()], &local_truth);
+            if f1 > best.1 {
+                best = (threshold, f1);
+            }
+        }
+
+        best
+    }
+
+    /// Count the clusters produced at each of a set of thresholds, discarding the clusters
+    /// themselves. Plotting the counts helps spot the "knee" at which to pick a threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `indices` - The elements to cluster.
+    /// * `thresholds` - The minimum similarities to cluster at.
+    ///
+    /// # Return
+    ///
+    /// Each threshold paired with its cluster count.
+    pub fn cluster_count_profile(
+        similarity_matrix: &SimilarityMatrix,
+        indices: &[Index],
+        thresholds: &[Similarity],
+    ) -> Vec<(Similarity, usize)> {
+        thresholds.iter()
+            .map(|&threshold| {
+                let clusters = Clusterer::cluster_drop_matrix(similarity_matrix.spin_off(indices, threshold));
+                (threshold, clusters.len())
+            })
+            .collect::<Vec<(Similarity, usize)>>()
+    }
+
+    /// Cluster a similarity matrix at each of a set of thresholds, writing each clustering to a
+    /// file in a directory. Files are named `clusters-<threshold>.txt` and hold one line per
+    /// cluster, as produced by a formatter.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity_matrix` - Similarity matrix to cluster.
+    /// * `elements` - The input set the matrix was built from.
+    /// * `thresholds` - The minimum similarities to cluster at.
+    /// * `dir` - The directory to write files to. Created if missing.
+    /// * `formatter` - The function formatting a cluster's line given its indices and the input set.
+    ///
+    /// # Return
+    ///
+    /// The result of writing all files.
+    pub fn sweep_to_dir<T, P, F>(
+        similarity_matrix: &SimilarityMatrix,
+        elements: &[T],
+        thresholds: &[Similarity],
+        dir: P,
+        formatter: F,
+    ) -> io::Result<()>
+        where
+            P: AsRef<Path>,
+            F: Fn(&[Index], &[T]) -> String,
+    {
+        assert_eq!(elements.len(), similarity_matrix.size(), "Element count must equal matrix size");
+
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let indices = (0..similarity_matrix.size()).collect::<Vec<Index>>();
+        for &threshold in thresholds {
+            let clusters = Clusterer::cluster_drop_matrix(similarity_matrix.spin_off(&indices, threshold));
+
+            let mut out = BufWriter::new(fs::File::create(dir.join(format!("clusters-{}.txt", threshold)))?);
+            for cluster in &clusters {
+                writeln!(out, "{}", formatter(cluster, elements))?;
+            }
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::MassEvaluator;
+    use crate::utils::unique_temp_path;
+
+    use super::*;
+    use super::super::tests::matrix_from;
+
+    #[test]
+    fn selects_best_threshold() {
+        let edges = vec![(0, 1, 0.9), (1, 2, 0.55), (3, 4, 0.8)];
+        let similarity_matrix = matrix_from(5, &edges);
+
+        let evaluator = MassEvaluator { resolution: -0.6 };
+        let (clustering, threshold, evaluation) =
+            Clusterer::best_clustering_scored(&similarity_matrix, &[0.5, 0.7, 0.85, 0.95], &evaluator);
+
+        assert_eq!(threshold, 0.7);
+        assert_eq!(clustering.clusters.len(), 3);
+        assert!((evaluation - (0.9 + 0.8 + 1.8)).abs() < 1e-9);
+
+        let clustering = Clusterer::best_clustering(&similarity_matrix, &[0.5, 0.7], &evaluator);
+        assert_eq!(clustering.clusters.len(), 3);
+    }
+
+    #[test]
+    fn profiles_cluster_count() {
+        let edges = vec![(0, 1, 0.9), (1, 2, 0.55), (3, 4, 0.8)];
+        let similarity_matrix = matrix_from(5, &edges);
+
+        let profile =
+            Clusterer::cluster_count_profile(&similarity_matrix, &[0, 1, 2, 3, 4], &[0.5, 0.7, 0.85, 0.95]);
+        assert_eq!(profile, vec![(0.5, 2), (0.7, 3), (0.85, 4), (0.95, 5)]);
+
+        let profile = Clusterer::cluster_count_profile(&similarity_matrix, &[0, 1, 2], &[0.5, 0.7]);
+        assert_eq!(profile, vec![(0.5, 1), (0.7, 2)]);
+    }
+
+    #[test]
+    fn sweeps_to_dir() {
+        let elements = vec!["a", "b", "c", "d", "e"];
+        let similarity_matrix = matrix_from(5, &[(0, 1, 0.9), (1, 2, 0.55), (3, 4, 0.8)]);
+        let dir = unique_temp_path("sweep-to-dir");
+
+        Clusterer::sweep_to_dir(&similarity_matrix, &elements, &[0.5, 0.85], &dir, |cluster, elements| {
+            let names = cluster.iter().map(|index| elements[*index]).collect::<Vec<&str>>();
+            format!("{},{}", cluster.len(), names.join(","))
+        }).unwrap();
+
+        let mut lines = fs::read_to_string(dir.join("clusters-0.85.txt")).unwrap()
+            .lines()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        lines.sort();
+        assert_eq!(lines, vec!["1,c", "1,d", "1,e", "2,a,b"]);
+        assert_eq!(fs::read_to_string(dir.join("clusters-0.5.txt")).unwrap().lines().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tunes_threshold_for_f1() {
+        // Labels group {0, 1, 2} and {3, 4}; 5 is unlabeled. Cutting above 0.7 loses the true 1-2
+        // edge, while the recursive split keeps 2 and 3 apart at all lower thresholds.
+        let edges = vec![(0, 1, 0.9), (1, 2, 0.7), (2, 3, 0.5), (3, 4, 0.8), (4, 5, 0.6)];
+        let similarity_matrix = matrix_from(6, &edges);
+        let truth = vec![Some(0), Some(0), Some(0), Some(1), Some(1), None];
+        let indices = (0..6).collect::<Vec<Index>>();
+
+        assert_eq!(Clusterer::tune_threshold_f1(&similarity_matrix, &indices, &truth), (0.5, 1.0));
+    }
+}
@@ -0,0 +1,138 @@
+//! This module contains a thin wrapper pairing an input set with its similarity matrix so that
+//! clustering can return actual elements rather than their indices.
+
+use crate::cluster::{Cluster, Clusterer, ClustererConfig};
+use crate::index_pair::IndexPair;
+use crate::sim_matrix::SimilarityMatrix;
+use crate::sim_metric::Similarity;
+
+/// An input set together with the similarity matrix built over it.
+#[derive(Debug)]
+pub struct Corpus<T> {
+    /// The elements to be clustered.
+    elements: Vec<T>,
+    /// The similarity matrix built over `elements`.
+    similarity_matrix: SimilarityMatrix,
+}
+
+/// Corpus implementation.
+impl<T> Corpus<T>
+    where T: Sync + Send
+{
+    /// Create a new `Corpus` by building the similarity matrix for a given input set.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply for clustering.
+    ///
+    /// # Return
+    ///
+    /// A new `Corpus` instance.
+    pub fn new<I, M>(
+        elements: Vec<T>,
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> Corpus<T>
+        where
+            I: Iterator<Item=IndexPair> + Send,
+            M: Fn(&T, &T) -> Similarity + Sync,
+    {
+        let similarity_matrix =
+            SimilarityMatrix::new(&elements, min_similarity, index_pair_iterator, similarity_metric);
+
+        Corpus { elements, similarity_matrix }
+    }
+
+    /// Return this corpus' elements.
+    pub fn elements(&self) -> &[T] {
+        &self.elements
+    }
+
+    /// Return this corpus' similarity matrix.
+    pub fn similarity_matrix(&self) -> &SimilarityMatrix {
+        &self.similarity_matrix
+    }
+
+    /// Cluster this corpus using the default configuration.
+    ///
+    /// # Return
+    ///
+    /// The clusters, each holding references to its member elements.
+    pub fn cluster(&self) -> Vec<Vec<&T>> {
+        self.cluster_with(&ClustererConfig::default())
+    }
+
+    /// Cluster this corpus using a given configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The clusterer configuration.
+    ///
+    /// # Return
+    ///
+    /// The clusters, each holding references to its member elements.
+    pub fn cluster_with(&self, config: &ClustererConfig) -> Vec<Vec<&T>> {
+        let clusters = Clusterer::new(config).run(&self.similarity_matrix);
+        self.resolve(&clusters)
+    }
+
+    /// Map index-based clusters to clusters of references to this corpus' elements.
+    ///
+    /// # Arguments
+    ///
+    /// * `clusters` - Clusters holding indices into this corpus.
+    ///
+    /// # Return
+    ///
+    /// The clusters, each holding references to its member elements.
+    pub fn resolve(&self, clusters: &[Cluster]) -> Vec<Vec<&T>> {
+        clusters.iter()
+            .map(|cluster|
+                cluster.iter()
+                    .map(|index| &self.elements[*index])
+                    .collect::<Vec<&T>>())
+            .collect::<Vec<Vec<&T>>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strsim::normalized_damerau_levenshtein;
+
+    use crate::index_pair::cartesian::CartesianIndexPairIterator;
+    use crate::utils::string_vec;
+
+    use super::*;
+
+    #[test]
+    fn clusters_elements() {
+        let names = string_vec(vec![
+            "alejandro", "alejo",
+            "martha", "marta",
+            "marlene", "marleny", "malrene",
+            "ricardo"
+        ]);
+        let size = names.len();
+
+        let corpus = Corpus::new(
+            names,
+            0.45,
+            &mut CartesianIndexPairIterator::new(size),
+            |t1, t2| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        let mut clusters = corpus.cluster();
+        clusters.sort();
+
+        assert_eq!(clusters, vec![
+            vec!["alejandro", "alejo"],
+            vec!["marlene", "marleny", "malrene"],
+            vec!["martha", "marta"],
+            vec!["ricardo"],
+        ]);
+    }
+}
@@ -1,18 +1,27 @@
 //! This module contains clustering evaluation metrics used to compare clustering quality.
 
+use std::collections::HashMap;
+
 use crate::cluster::ClusteringResult;
+use crate::sim_metric::Similarity;
 
 /// The `f64` type for cluster evaluation.
-type ClusterEvaluation = f64;
+pub type ClusterEvaluation = f64;
 
-/// A metric for measuring the quality of a `Clustering` result.
+/// A metric for measuring the quality of a `Clustering` result. Methods take `&self` so that
+/// evaluators can carry parameters, such as `MassEvaluator`'s resolution.
 pub trait ClusterEvaluator {
-    /// Compare two `Clustering` results to measure which one is "best" according to this evaluator.
+    /// Measure the quality of a `Clustering` result, to be compared with `best_of`.
     ///
     /// # Arguments
     ///
-    /// `clustering` - The `Clustering` result to be evaluated.
-    fn evaluate(clustering: &ClusteringResult) ->  ClusterEvaluation;
+    /// * `clustering` - The `Clustering` result to be evaluated.
+    ///
+    /// # Return
+    ///
+    /// The clustering's evaluation.
+    fn evaluate(&self, clustering: &ClusteringResult) -> ClusterEvaluation;
+
     /// Ascertain whether a given `ClusterEvaluation` is "better" than another.
     ///
     /// # Arguments
@@ -23,5 +32,200 @@ pub trait ClusterEvaluator {
     /// # Return
     ///
     /// A boolean value indicating whether `e1` is a better value than `e2`.
-    fn best_of(e1: ClusterEvaluation, e2: ClusterEvaluation) -> bool;
-}
\ No newline at end of file
+    fn best_of(&self, e1: ClusterEvaluation, e2: ClusterEvaluation) -> bool;
+}
+
+/// Evaluator scoring a clustering by its total intra-cluster similarity minus a fixed penalty
+/// per cluster. Larger `resolution` values favor fewer, larger clusters; smaller (or negative)
+/// ones favor more, smaller clusters.
+pub struct MassEvaluator {
+    /// The penalty charged for each cluster.
+    pub resolution: f64,
+}
+
+impl ClusterEvaluator for MassEvaluator {
+    fn evaluate(&self, clustering: &ClusteringResult) -> ClusterEvaluation {
+        let mass = clustering.cluster_internal_weight().iter().sum::<Similarity>();
+
+        mass - self.resolution * clustering.clusters.len() as f64
+    }
+
+    fn best_of(&self, e1: ClusterEvaluation, e2: ClusterEvaluation) -> bool {
+        e1 > e2
+    }
+}
+
+/// Evaluator computing the Dunn index: the minimum separation between clusters divided by the
+/// maximum cluster diameter, where distances are `1 - similarity`. Separation between two
+/// clusters is their single-linkage distance and a cluster's diameter is the largest distance
+/// between two of its elements. Pairs not stored in the matrix are at distance `1.0`.
+///
+/// Clusterings with fewer than two clusters have no separation and evaluate to `0.0`. Clusterings
+/// whose diameters are all zero (such as all-singleton ones) evaluate to `f64::INFINITY`. Noise is
+/// ignored.
+pub struct DunnEvaluator;
+
+impl ClusterEvaluator for DunnEvaluator {
+    fn evaluate(&self, clustering: &ClusteringResult) -> ClusterEvaluation {
+        if clustering.clusters.len() < 2 {
+            return 0.0;
+        }
+
+        let labels = clustering.labels();
+
+        let mut max_cross_similarity: Similarity = 0.0;
+        let mut intra_pair_counts = vec![0usize; clustering.clusters.len()];
+        let mut min_intra_similarities = vec![1.0; clustering.clusters.len()];
+
+        for (index, row) in clustering.similarity_matrix.rows.iter().enumerate() {
+            let label = match labels[index] {
+                Some(label) => label,
+                None => continue,
+            };
+            for score in row.scores.iter().filter(|score| score.sibling_index > index) {
+                match labels[score.sibling_index] {
+                    Some(sibling_label) if sibling_label == label => {
+                        intra_pair_counts[label] += 1;
+                        min_intra_similarities[label] = score.similarity.min(min_intra_similarities[label]);
+                    }
+                    Some(_) => max_cross_similarity = max_cross_similarity.max(score.similarity),
+                    None => {}
+                }
+            }
+        }
+
+        let max_diameter =
+            clustering.clusters.iter()
+                .enumerate()
+                .map(|(position, cluster)| {
+                    let pair_count = cluster.len() * (cluster.len() - 1) / 2;
+                    if pair_count == 0 {
+                        0.0
+                    } else if intra_pair_counts[position] < pair_count {
+                        1.0
+                    } else {
+                        1.0 - min_intra_similarities[position]
+                    }
+                })
+                .fold(0.0, f64::max);
+
+        let min_separation = 1.0 - max_cross_similarity;
+        if max_diameter == 0.0 {
+            f64::INFINITY
+        } else {
+            min_separation / max_diameter
+        }
+    }
+
+    fn best_of(&self, e1: ClusterEvaluation, e2: ClusterEvaluation) -> bool {
+        e1 > e2
+    }
+}
+
+/// Compute the pairwise F1 score of a clustering against partial ground truth. Only pairs of
+/// labeled elements count: a pair is a true positive if both elements share a cluster and a
+/// label, a false positive if they share a cluster but not a label, and a false negative if they
+/// share a label but not a cluster.
+///
+/// # Arguments
+///
+/// * `predicted` - Each element's cluster, `None` for noise (which shares a cluster with nothing).
+/// * `truth` - Each element's ground truth label, `None` for unlabeled elements.
+///
+/// # Return
+///
+/// The harmonic mean of pairwise precision and recall, or `0.0` if no labeled pair shares a
+/// cluster or a label.
+pub fn pairwise_f1(predicted: &[Option<usize>], truth: &[Option<usize>]) -> f64 {
+    assert_eq!(predicted.len(), truth.len(), "Predicted and truth labels must have the same length");
+
+    let pair_count = |count: &usize| count * count.saturating_sub(1) / 2;
+
+    let mut truth_counts: HashMap<usize, usize> = HashMap::new();
+    let mut predicted_counts: HashMap<usize, usize> = HashMap::new();
+    let mut joint_counts: HashMap<(usize, usize), usize> = HashMap::new();
+    for (predicted, truth) in predicted.iter().zip(truth.iter()) {
+        if let Some(truth) = truth {
+            *truth_counts.entry(*truth).or_default() += 1;
+            if let Some(predicted) = predicted {
+                *predicted_counts.entry(*predicted).or_default() += 1;
+                *joint_counts.entry((*predicted, *truth)).or_default() += 1;
+            }
+        }
+    }
+
+    let true_positives = joint_counts.values().map(pair_count).sum::<usize>();
+    let same_truth = truth_counts.values().map(pair_count).sum::<usize>();
+    let same_predicted = predicted_counts.values().map(pair_count).sum::<usize>();
+
+    if same_truth + same_predicted == 0 {
+        0.0
+    } else {
+        2.0 * true_positives as f64 / (same_truth + same_predicted) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Index;
+    use crate::sim_matrix::SimilarityMatrix;
+
+    use super::*;
+
+    fn clustering_of(clusters: Vec<Vec<Index>>) -> ClusteringResult {
+        let triplets = vec![(0, 1, 0.9), (0, 2, 0.5), (1, 2, 0.7), (3, 4, 0.8), (2, 3, 0.2)];
+        ClusteringResult::new(clusters, vec![], SimilarityMatrix::from_triplets(5, 0.0, triplets))
+    }
+
+    #[test]
+    fn evaluates_similarity_mass() {
+        let clustering = clustering_of(vec![vec![0, 1, 2], vec![3, 4]]);
+
+        let evaluation = MassEvaluator { resolution: 0.5 }.evaluate(&clustering);
+
+        assert!((evaluation - (2.1 + 0.8 - 1.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn resolution_biases_cluster_count() {
+        let coarse = clustering_of(vec![vec![0, 1, 2, 3, 4]]);
+        let fine = clustering_of(vec![vec![0, 1, 2], vec![3, 4]]);
+
+        let coarsening = MassEvaluator { resolution: 0.5 };
+        assert!(coarsening.best_of(coarsening.evaluate(&coarse), coarsening.evaluate(&fine)));
+
+        let refining = MassEvaluator { resolution: -0.5 };
+        assert!(refining.best_of(refining.evaluate(&fine), refining.evaluate(&coarse)));
+    }
+
+    #[test]
+    fn evaluates_dunn_index() {
+        let evaluator = DunnEvaluator;
+
+        let clustering = clustering_of(vec![vec![0, 1, 2], vec![3, 4]]);
+        assert!((evaluator.evaluate(&clustering) - 0.8 / 0.5).abs() < 1e-9);
+
+        // Diameter of {0, 1, 2, 3} is 1.0 as 3 isn't stored as similar to 0 or 1
+        let clustering = clustering_of(vec![vec![0, 1, 2, 3], vec![4]]);
+        assert!((evaluator.evaluate(&clustering) - 0.2).abs() < 1e-9);
+
+        let clustering = clustering_of(vec![vec![0, 1], vec![2], vec![3, 4]]);
+        assert!((evaluator.evaluate(&clustering) - 0.3 / 0.2).abs() < 1e-9);
+
+        assert_eq!(evaluator.evaluate(&clustering_of(vec![vec![0, 1, 2, 3, 4]])), 0.0);
+        assert_eq!(evaluator.evaluate(&clustering_of(vec![vec![0], vec![1], vec![2], vec![3], vec![4]])), f64::INFINITY);
+    }
+
+    #[test]
+    fn computes_pairwise_f1() {
+        // True pairs: 0-1, 0-2, 1-2, 3-4. Predicted pairs among labeled: 0-1, 2-3. Element 5 is
+        // unlabeled and element 4 is noise.
+        let truth = vec![Some(0), Some(0), Some(0), Some(1), Some(1), None];
+        let predicted = vec![Some(0), Some(0), Some(1), Some(1), None, Some(0)];
+
+        // One true positive, 2 predicted pairs, 4 true pairs: F1 = 2 / (2 + 4).
+        assert_eq!(pairwise_f1(&predicted, &truth), 2.0 / 6.0);
+        assert_eq!(pairwise_f1(&truth, &truth), 1.0);
+        assert_eq!(pairwise_f1(&[Some(0), Some(1)], &[Some(0), Some(1)]), 0.0);
+    }
+}
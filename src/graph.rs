@@ -0,0 +1,175 @@
+//! This module abstracts the neighbor source traversed by the clusterer, so that graphs too large
+//! to materialize as a `SimilarityMatrix` (such as disk- or database-backed ones) can be clustered
+//! by answering neighbor queries on demand.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::{Index, Size};
+use crate::sim_matrix::{rank_weights, RankMode, Row, Score, SimilarityMatrix};
+use crate::sim_metric::Similarity;
+
+/// A symmetric similarity graph over the elements of an input set.
+pub trait Graph {
+    /// Return the number of elements in this graph.
+    fn size(&self) -> Size;
+
+    /// Return an element's neighbors along with their similarity to it. Neighbor relations must
+    /// be symmetric: `j` is a neighbor of `i` with a given similarity if and only if `i` is a
+    /// neighbor of `j` with the same similarity.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The element whose neighbors are sought.
+    ///
+    /// # Return
+    ///
+    /// The element's neighbors paired with their similarity.
+    fn neighbors(&self, index: Index) -> Vec<(Index, Similarity)>;
+
+    /// Return an element's neighbors as a `Row`. Graphs already storing rows should override
+    /// this to avoid building a new one on each call.
+    fn row(&self, index: Index) -> Cow<'_, Row> {
+        let scores =
+            self.neighbors(index).into_iter()
+                .map(|(sibling_index, similarity)| Score { sibling_index, similarity })
+                .collect::<Vec<Score>>();
+        Cow::Owned(Row::new(scores))
+    }
+
+    /// Rank this graph's indices by weight, as per `SimilarityMatrix::rank_by_weight`.
+    fn rank_by_weight(&self, mode: RankMode) -> Vec<Index> {
+        rank_weights(
+            mode,
+            (0..self.size())
+                .map(|index| {
+                    let (sibling_count, similarity_sum) = self.row(index).weight();
+                    (index, sibling_count, similarity_sum)
+                })
+                .collect::<Vec<(Index, Size, Similarity)>>())
+    }
+
+    /// Materialize the subgraph induced by a subset of this graph's elements, as needed to
+    /// recursively split a cluster.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - The elements of the subgraph. Must not be empty.
+    ///
+    /// # Return
+    ///
+    /// A similarity matrix whose indices follow the order of `indices`.
+    fn subgraph(&self, indices: &[Index]) -> SimilarityMatrix {
+        let positions =
+            indices.iter()
+                .enumerate()
+                .map(|(position, index)| (*index, position))
+                .collect::<HashMap<Index, Index>>();
+
+        let similarity_triplets =
+            indices.iter()
+                .enumerate()
+                .flat_map(|(position, index)| {
+                    self.neighbors(*index).into_iter()
+                        .filter_map(|(sibling_index, similarity)|
+                            positions.get(&sibling_index)
+                                .filter(|sibling_position| **sibling_position > position)
+                                .map(|sibling_position| (position, *sibling_position, similarity)))
+                        .collect::<Vec<(Index, Index, Similarity)>>()
+                })
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        SimilarityMatrix::from_triplets(indices.len(), 0.0, similarity_triplets)
+    }
+}
+
+/// `Graph` implementation for the materialized similarity matrix.
+impl Graph for SimilarityMatrix {
+    fn size(&self) -> Size {
+        SimilarityMatrix::size(self)
+    }
+
+    fn neighbors(&self, index: Index) -> Vec<(Index, Similarity)> {
+        self.rows[index].scores.iter()
+            .map(|score| (score.sibling_index, score.similarity))
+            .collect::<Vec<(Index, Similarity)>>()
+    }
+
+    fn row(&self, index: Index) -> Cow<'_, Row> {
+        Cow::Borrowed(&self.rows[index])
+    }
+
+    fn subgraph(&self, indices: &[Index]) -> SimilarityMatrix {
+        self.spin_off(indices, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cluster::{Clusterer, ClustererConfig};
+
+    use super::*;
+
+    /// A graph answering neighbor queries by scanning an edge list.
+    struct AdjacencyGraph {
+        edges: Vec<(Index, Index, Similarity)>,
+        size: Size,
+    }
+
+    impl Graph for AdjacencyGraph {
+        fn size(&self) -> Size {
+            self.size
+        }
+
+        fn neighbors(&self, index: Index) -> Vec<(Index, Similarity)> {
+            self.edges.iter()
+                .filter_map(|(row, column, similarity)|
+                    if *row == index {
+                        Some((*column, *similarity))
+                    } else if *column == index {
+                        Some((*row, *similarity))
+                    } else {
+                        None
+                    })
+                .collect::<Vec<(Index, Similarity)>>()
+        }
+    }
+
+    #[test]
+    fn materializes_subgraph() {
+        let graph = AdjacencyGraph { edges: vec![(0, 1, 0.5), (1, 3, 0.75), (2, 3, 0.25)], size: 4 };
+
+        let subgraph = graph.subgraph(&[3, 1, 0]);
+
+        assert_eq!(subgraph.size(), 3);
+        assert_eq!(subgraph[0][1], 0.75);
+        assert_eq!(subgraph[1][2], 0.5);
+        assert_eq!(subgraph[0][2], 0.0);
+    }
+
+    #[test]
+    fn ranks_like_matrix() {
+        let edges = vec![(0, 1, 0.5), (1, 3, 0.75), (2, 3, 0.25), (1, 2, 0.125)];
+        let graph = AdjacencyGraph { edges: edges.clone(), size: 4 };
+        let similarity_matrix = SimilarityMatrix::from_triplets(4, 0.0, edges);
+
+        assert_eq!(graph.rank_by_weight(RankMode::default()), similarity_matrix.rank_by_weight(RankMode::default()));
+        assert_eq!(
+            Graph::rank_by_weight(&similarity_matrix, RankMode::ByMeanSimilarity),
+            similarity_matrix.rank_by_weight(RankMode::ByMeanSimilarity),
+        );
+    }
+
+    #[test]
+    fn clusters_like_matrix() {
+        let edges = vec![
+            (0, 1, 0.9), (0, 2, 0.8), (0, 3, 0.7), (1, 2, 0.6), (3, 4, 0.95), (5, 6, 0.5),
+        ];
+        let graph = AdjacencyGraph { edges: edges.clone(), size: 7 };
+        let similarity_matrix = SimilarityMatrix::from_triplets(7, 0.0, edges);
+
+        let mut clusterer = Clusterer::new(&ClustererConfig::default());
+
+        assert_eq!(clusterer.run(&graph), clusterer.run(&similarity_matrix));
+    }
+}
@@ -4,9 +4,31 @@ use crate::Index;
 
 pub mod ngrams;
 pub mod cartesian;
+pub mod sampled;
+
+pub use self::ngrams::{ngrams, positional_ngrams};
 
 /// Pair of indices corresponding to candidate elements to be considered for clustering together.
 pub type IndexPair = (Index, Index);
 
-/// Iterator over index pairs to be considered for clustering together.
-pub type IndexPairIterator = dyn Iterator<Item=IndexPair> + Send;
+/// Source of index pairs to be considered for clustering together. Implemented by every sendable
+/// iterator over `IndexPair`s.
+pub trait IndexPairSource: Iterator<Item=IndexPair> + Send {}
+
+impl<I: Iterator<Item=IndexPair> + Send> IndexPairSource for I {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::cartesian::CartesianIndexPairIterator;
+
+    fn count_pairs(pairs: impl IndexPairSource) -> usize {
+        pairs.count()
+    }
+
+    #[test]
+    fn accepts_any_sendable_pair_iterator() {
+        assert_eq!(count_pairs(CartesianIndexPairIterator::new(4)), 6);
+        assert_eq!(count_pairs(vec![(0, 1), (1, 2)].into_iter()), 2);
+    }
+}
@@ -1,23 +1,81 @@
 //! This module contains an implementation of index pair iterator for strings. String pairs are
 //! selected based on sharing one or more n-grams of a given length.
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
 
 use crate::{Index, Size};
 
 use super::IndexPair;
 
-/// The NGram pair iterator structure
+/// The NGram pair iterator structure. Pairs are yielded in ascending `(i, j)` order.
 #[derive(Debug)]
 pub struct NGramPairs {
     /// The collected index pairs.
     pairs: Vec<IndexPair>,
+    /// The n-grams shared by each pair, parallel to `pairs`. Only recorded in provenance mode.
+    provenance: Option<Vec<Vec<String>>>,
     /// The current iteration index.
     current_index: Index,
+    /// Statistics on the blocking key buckets expanded into pairs.
+    bucket_stats: BucketStats,
+}
+
+/// Statistics on the buckets of strings sharing a blocking key, useful for gauging how much
+/// work capping bucket sizes saves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BucketStats {
+    /// The number of distinct blocking keys, i.e. of buckets.
+    pub buckets: Size,
+    /// The number of buckets skipped for exceeding the maximum bucket size.
+    pub skipped_buckets: Size,
+    /// The number of pair expansions avoided by skipping buckets. Pairs sharing several skipped
+    /// buckets are counted once per bucket.
+    pub skipped_pairs: usize,
+}
+
+/// Report on how many strings are too short to yield any n-gram of a given length. Such strings
+/// share no block with any other string and can only end up as singletons.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NGramCoverage {
+    /// The number of strings examined.
+    pub size: Size,
+    /// The n-gram length examined.
+    pub ngram_length: Size,
+    /// The number of strings shorter than `ngram_length`.
+    pub short_strings: Size,
+}
+
+impl NGramCoverage {
+    /// Return the fraction of strings yielding no n-grams, between `0.0` and `1.0`.
+    pub fn short_ratio(&self) -> f64 {
+        if self.size == 0 {
+            0.0
+        } else {
+            self.short_strings as f64 / self.size as f64
+        }
+    }
+
+    /// Return whether most strings yield no n-grams, in which case blocking will pair few or none.
+    pub fn is_degenerate(&self) -> bool {
+        self.short_strings * 2 > self.size
+    }
+}
+
+impl fmt::Display for NGramCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.0}% of elements are shorter than ngram_length {} and produce no grams",
+            self.short_ratio() * 100.0,
+            self.ngram_length,
+        )
+    }
 }
 
 /// NGram implementation.
 impl NGramPairs {
-    /// Create a new `NGramPairs` instance
+    /// Check up front how many strings are too short for a given n-gram length.
     ///
     /// # Arguments
     ///
@@ -26,55 +84,258 @@ impl NGramPairs {
     ///
     /// # Return
     ///
+    /// * The coverage report.
+    pub fn coverage(strings: &[String], ngram_length: Size) -> NGramCoverage {
+        assert!(ngram_length > 0);
+
+        let short_strings =
+            strings.iter()
+                .filter(|string| string.chars().count() < ngram_length)
+                .count();
+
+        NGramCoverage { size: strings.len(), ngram_length, short_strings }
+    }
+
+    /// Create a new `NGramPairs` instance pairing strings that share at least one n-gram.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `ngram_length` - The length of n-grams to build in ascertaining commonality.
+    ///
+    /// # Return
+    ///
+    /// * A new `NGramPairs` instance.
+    pub fn new(strings: &[String], ngram_length: Size) -> NGramPairs {
+        NGramPairs::with_min_shared(strings, ngram_length, 1)
+    }
+
+    /// Create a new `NGramPairs` instance pairing strings that share at least `min_shared`
+    /// distinct n-grams. Requiring more than one shared n-gram sharply reduces false candidates
+    /// among long strings.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `ngram_length` - The length of n-grams to build in ascertaining commonality.
+    /// * `min_shared` - The minimum number of distinct n-grams two strings must share.
+    ///
+    /// # Return
+    ///
     /// * A new `NGramPairs` instance.
-    pub fn new(strings: &Vec<String>, ngram_length: Size) -> NGramPairs {
+    pub fn with_min_shared(strings: &[String], ngram_length: Size, min_shared: usize) -> NGramPairs {
         assert!(ngram_length > 0);
 
-        let size = strings.len();
+        NGramPairs::from_keys(strings, |string| ngrams(string, ngram_length), min_shared)
+    }
 
-        let mut ngram_to_indices = HashMap::new();
-        (0..size)
-            .flat_map(|index| {
-                ngrams(&strings[index], ngram_length)
-                    .iter()
-                    .map(|ngram| (ngram.clone(), index))
-                    .collect::<Vec<(String, Index)>>()
-            })
-            .for_each(|(ngram, index)| {
-                ngram_to_indices
-                    .entry(ngram)
-                    .or_insert_with(|| HashSet::new())
+    /// Create a new `NGramPairs` instance as per `with_min_shared`, skipping n-grams shared by
+    /// more than `max_bucket_size` strings. Ubiquitous n-grams (stop-grams) expand into huge
+    /// numbers of pairs that rarely match; ignoring them can speed up blocking considerably.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `ngram_length` - The length of n-grams to build in ascertaining commonality.
+    /// * `min_shared` - The minimum number of distinct n-grams two strings must share.
+    /// * `max_bucket_size` - The maximum number of strings an n-gram may be shared by and still
+    ///   pair them. Must be positive.
+    ///
+    /// # Return
+    ///
+    /// * A new `NGramPairs` instance. Skipped buckets are reported by `bucket_stats`.
+    pub fn with_max_bucket_size(
+        strings: &[String],
+        ngram_length: Size,
+        min_shared: usize,
+        max_bucket_size: Size,
+    ) -> NGramPairs {
+        assert!(ngram_length > 0);
+        assert!(max_bucket_size > 0, "Maximum bucket size must be positive");
+
+        NGramPairs::from_capped_keys(
+            strings,
+            |string| ngrams(string, ngram_length),
+            min_shared,
+            Some(max_bucket_size),
+            None,
+        )
+    }
+
+    /// Create a new `NGramPairs` instance as per `with_min_shared`, additionally recording the
+    /// n-grams shared by each pair. Provenance helps spot ubiquitous n-grams generating most of
+    /// the spurious candidates; retrieve it with `pairs_with_provenance`.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `ngram_length` - The length of n-grams to build in ascertaining commonality.
+    /// * `min_shared` - The minimum number of distinct n-grams two strings must share.
+    ///
+    /// # Return
+    ///
+    /// * A new `NGramPairs` instance in provenance mode.
+    pub fn with_provenance(strings: &[String], ngram_length: Size, min_shared: usize) -> NGramPairs {
+        assert!(ngram_length > 0);
+
+        NGramPairs::from_capped_keys(
+            strings,
+            |string| ngrams(string, ngram_length),
+            min_shared,
+            None,
+            Some(|ngram: &String| ngram.clone()),
+        )
+    }
+
+    /// Return every pair along with the n-grams its strings share, regardless of iteration
+    /// progress.
+    ///
+    /// # Return
+    ///
+    /// * The pairs with their sorted shared n-grams. Panics unless created by `with_provenance`.
+    pub fn pairs_with_provenance(&self) -> Vec<(IndexPair, Vec<String>)> {
+        let provenance = self.provenance.as_ref().expect("Pairs created without provenance");
+
+        self.pairs.iter()
+            .copied()
+            .zip(provenance.iter().cloned())
+            .collect::<Vec<(IndexPair, Vec<String>)>>()
+    }
+
+    /// Return the statistics on the buckets expanded into pairs.
+    pub fn bucket_stats(&self) -> &BucketStats {
+        &self.bucket_stats
+    }
+
+    /// Create a new `NGramPairs` instance pairing strings that share an n-gram at the same
+    /// position. Positional n-grams trade recall for precision: they prune the spurious
+    /// candidates long strings produce by sharing n-grams at unrelated positions, but miss pairs
+    /// whose similar parts are shifted by insertions or deletions.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `ngram_length` - The length of n-grams to build in ascertaining commonality.
+    ///
+    /// # Return
+    ///
+    /// * A new `NGramPairs` instance.
+    pub fn positional(strings: &[String], ngram_length: Size) -> NGramPairs {
+        assert!(ngram_length > 0);
+
+        NGramPairs::from_keys(strings, |string| positional_ngrams(string, ngram_length), 1)
+    }
+
+    /// Create a new `NGramPairs` instance blocking on byte n-grams packed into integers, which
+    /// avoids allocating a string per n-gram and speeds up hashing. On ASCII input this yields
+    /// the same pairs as `with_min_shared`.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `ngram_length` - The length of n-grams to build in ascertaining commonality, from 1 to 4.
+    /// * `min_shared` - The minimum number of distinct n-grams two strings must share.
+    ///
+    /// # Return
+    ///
+    /// * A new `NGramPairs` instance.
+    pub fn packed(strings: &[String], ngram_length: Size, min_shared: usize) -> NGramPairs {
+        assert!(ngram_length > 0 && ngram_length <= 4, "Packed n-gram length must be between 1 and 4");
+
+        NGramPairs::from_keys(strings, |string| packed_ngrams(string, ngram_length), min_shared)
+    }
+
+    /// Create a new `NGramPairs` instance pairing strings that share at least `min_shared`
+    /// distinct blocking keys, as extracted by an arbitrary function.
+    ///
+    /// # Arguments
+    ///
+    /// * `strings` - Reference to a vector of strings.
+    /// * `keys` - The function extracting each string's (possibly duplicate) blocking keys.
+    /// * `min_shared` - The minimum number of distinct keys two strings must share.
+    ///
+    /// # Return
+    ///
+    /// * A new `NGramPairs` instance.
+    pub fn from_keys<K, F>(strings: &[String], keys: F, min_shared: usize) -> NGramPairs
+        where
+            K: Eq + Hash,
+            F: Fn(&str) -> Vec<K>,
+    {
+        NGramPairs::from_capped_keys(strings, keys, min_shared, None, None)
+    }
+
+    /// Create a new `NGramPairs` instance as per `from_keys`, skipping keys shared by more than
+    /// `max_bucket_size` strings, if given. If a key description function is given, the sorted
+    /// descriptions of the keys shared by each pair are recorded as its provenance.
+    fn from_capped_keys<K, F>(
+        strings: &[String],
+        keys: F,
+        min_shared: usize,
+        max_bucket_size: Option<Size>,
+        describe_key: Option<fn(&K) -> String>,
+    ) -> NGramPairs
+        where
+            K: Eq + Hash,
+            F: Fn(&str) -> Vec<K>,
+    {
+        assert!(min_shared > 0);
+
+        let mut key_to_indices: HashMap<K, HashSet<Index>> = HashMap::new();
+        for (index, string) in strings.iter().enumerate() {
+            for key in keys(string) {
+                key_to_indices
+                    .entry(key)
+                    .or_default()
                     .insert(index);
+            }
+        }
+
+        let mut bucket_stats = BucketStats { buckets: key_to_indices.len(), ..Default::default() };
+        if let Some(max_bucket_size) = max_bucket_size {
+            key_to_indices.retain(|_, indices| {
+                let skipped = indices.len() > max_bucket_size;
+                if skipped {
+                    bucket_stats.skipped_buckets += 1;
+                    bucket_stats.skipped_pairs += indices.len() * (indices.len() - 1) / 2;
+                }
+                !skipped
             });
+        }
 
-        let mut index_to_ngrams: HashMap<Index, HashSet<Index>> = HashMap::new();
-        ngram_to_indices.iter().for_each(|(ngram, indices)| {
+        // Each pair's shared key count along with the descriptions of those keys, if requested
+        let mut shared_keys: HashMap<IndexPair, (usize, Vec<String>)> = HashMap::new();
+        key_to_indices.iter().for_each(|(key, indices)| {
+            let description = describe_key.map(|describe_key| describe_key(key));
             indices.iter().for_each(|index| {
-                ngram_to_indices
-                    .get(ngram)
-                    .unwrap()
+                indices
                     .iter()
                     .filter(|sibling_index| **sibling_index > *index)
                     .for_each(|sibling_index| {
-                        index_to_ngrams
-                            .entry(*index)
-                            .or_insert_with(|| HashSet::new())
-                            .insert(*sibling_index);
+                        let (shared, descriptions) = shared_keys.entry((*index, *sibling_index)).or_default();
+                        *shared += 1;
+                        if let Some(description) = &description {
+                            descriptions.push(description.clone());
+                        }
                     });
             });
         });
 
-        let pairs: Vec<IndexPair> = index_to_ngrams
-            .iter()
-            .flat_map(|(index, sibling_indices)| {
-                sibling_indices
-                    .iter()
-                    .map(|sibling_index| (*index, *sibling_index))
-                    .collect::<Vec<IndexPair>>()
+        let mut pairs_with_keys = shared_keys
+            .into_iter()
+            .filter(|(_, (shared, _))| *shared >= min_shared)
+            .map(|(pair, (_, mut descriptions))| {
+                descriptions.sort_unstable();
+                (pair, descriptions)
             })
-            .collect::<Vec<IndexPair>>();
+            .collect::<Vec<(IndexPair, Vec<String>)>>();
+        // Hash map iteration order varies from run to run
+        pairs_with_keys.sort_unstable_by_key(|(pair, _)| *pair);
 
-        NGramPairs { pairs, current_index: 0 }
+        let (pairs, descriptions): (Vec<IndexPair>, Vec<Vec<String>>) = pairs_with_keys.into_iter().unzip();
+        let provenance = describe_key.map(|_| descriptions);
+
+        NGramPairs { pairs, provenance, current_index: 0, bucket_stats }
     }
 }
 
@@ -95,29 +356,70 @@ impl Iterator for NGramPairs {
 }
 
 
-/// Divide a string into a set of (possibly duplicate) ngrams of a given length.
+/// Divide a string into a set of (possibly duplicate) ngrams of a given length. Lengths are
+/// measured in characters, not bytes, so non-ASCII strings are divided correctly.
 ///
 /// # Arguments
 ///
 /// * `string` - Reference to string from which to extract n-grams.
+/// * `ngram_length` - The length of the n-grams to extract.
 ///
 /// # Return
 ///
-/// A vector of strings containing all n-grams of the given length.
-fn ngrams(string: &String, ngram_length: Size) -> Vec<String> {
-    let last = string.len() - ngram_length + 1;
-    (0..last)
-        .map(|start| {
-            let end = start + ngram_length;
-            string.chars().take(end).skip(start).collect::<String>()
-        })
-        .filter(|ngram| ngram.len() == ngram_length)
+/// A vector of strings containing all n-grams of the given length. Strings shorter than
+/// `ngram_length` yield no n-grams.
+pub fn ngrams(string: &str, ngram_length: Size) -> Vec<String> {
+    positional_ngrams(string, ngram_length).into_iter()
+        .map(|(_, ngram)| ngram)
         .collect::<Vec<String>>()
 }
 
+/// Divide a string into n-grams of a given length paired with their starting character position.
+///
+/// # Arguments
+///
+/// * `string` - Reference to string from which to extract n-grams.
+/// * `ngram_length` - The length of the n-grams to extract.
+///
+/// # Return
+///
+/// A vector containing all n-grams of the given length, each preceded by its position. Strings
+/// shorter than `ngram_length` yield no n-grams.
+pub fn positional_ngrams(string: &str, ngram_length: Size) -> Vec<(usize, String)> {
+    assert!(ngram_length > 0);
+
+    string.chars()
+        .collect::<Vec<char>>()
+        .windows(ngram_length)
+        .map(|window| window.iter().collect::<String>())
+        .enumerate()
+        .collect::<Vec<(usize, String)>>()
+}
+
+/// Divide a string into byte n-grams of a given length, each packed big-endian into a `u32`.
+/// For ASCII strings these correspond one to one with the n-grams returned by `ngrams`.
+///
+/// # Arguments
+///
+/// * `string` - Reference to string from which to extract n-grams.
+/// * `ngram_length` - The length of the n-grams to extract, from 1 to 4.
+///
+/// # Return
+///
+/// A vector containing all packed n-grams of the given length. Strings shorter than
+/// `ngram_length` yield no n-grams.
+pub fn packed_ngrams(string: &str, ngram_length: Size) -> Vec<u32> {
+    assert!(ngram_length > 0 && ngram_length <= 4, "Packed n-gram length must be between 1 and 4");
+
+    string.as_bytes()
+        .windows(ngram_length)
+        .map(|window| window.iter().fold(0u32, |packed, byte| (packed << 8) | u32::from(*byte)))
+        .collect::<Vec<u32>>()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::utils::string_vec;
+    use crate::utils::{read_file_lines, string_vec};
 
     use super::*;
 
@@ -134,17 +436,54 @@ mod tests {
         assert_eq!(ngrams_3, string_vec(vec![
             "rus", "ust", "sti", "tin", "ino", "nom", "omi", "mic", "ico", "con",
         ]));
+
+        assert!(ngrams("ru", 3).is_empty());
+        assert_eq!(ngrams("acuña", 2), string_vec(vec!["ac", "cu", "uñ", "ña"]));
+        assert!(ngrams("ña", 3).is_empty());
+    }
+
+    #[test]
+    fn builds_positional_ngrams() {
+        assert_eq!(
+            positional_ngrams("añil", 2),
+            vec![(0, String::from("añ")), (1, String::from("ñi")), (2, String::from("il"))]);
+        assert!(positional_ngrams("añ", 3).is_empty());
+    }
+
+    #[test]
+    fn skips_oversized_buckets() {
+        // "an" is shared by all four strings, "ia" by three and "ma" and "na" by two each.
+        let strings = string_vec(vec!["mana", "mania", "iana", "tian"]);
+
+        let uncapped = NGramPairs::new(&strings, 2);
+        assert_eq!(uncapped.bucket_stats().skipped_buckets, 0);
+        assert_eq!(uncapped.count(), 6);
+
+        let capped = NGramPairs::with_max_bucket_size(&strings, 2, 1, 3);
+        assert_eq!(capped.bucket_stats().skipped_buckets, 1);
+        assert_eq!(capped.bucket_stats().skipped_pairs, 6);
+        assert_eq!(capped.collect::<Vec<IndexPair>>(), vec![(0, 1), (0, 2), (1, 2), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn builds_positional_pairs() {
+        let strings = string_vec(vec!["abcd", "xbcd", "bcda"]);
+
+        let pairs = NGramPairs::positional(&strings, 2).collect::<Vec<IndexPair>>();
+
+        assert_eq!(pairs, vec![(0, 1)]);
+        assert_eq!(NGramPairs::new(&strings, 2).count(), 3);
     }
 
     #[test]
     fn builds_pairs_correctly() {
         let names = string_vec(vec!["alejandro", "marlene", "martha", "ricardo"]);
-        let expected_pairs = vec![
+        let expected_pairs = [
             (0usize, 1usize),
             (1, 2),
             (1, 3),
             (2, 3),
-        ].iter().map(|p| *p).collect::<HashSet<IndexPair>>();
+        ].iter().copied().collect::<HashSet<IndexPair>>();
 
         let actual_pairs =
             NGramPairs::new(&names, 2)
@@ -152,5 +491,88 @@ mod tests {
 
         assert_eq!(actual_pairs, expected_pairs);
     }
-}
 
+    #[test]
+    fn orders_pairs_deterministically() {
+        let names = read_file_lines(String::from("data/surnames.txt"), 200);
+
+        let pairs_1 = NGramPairs::new(&names, 2).collect::<Vec<IndexPair>>();
+        let pairs_2 = NGramPairs::new(&names, 2).collect::<Vec<IndexPair>>();
+
+        assert_eq!(pairs_1, pairs_2);
+        assert!(pairs_1.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn requires_min_shared_ngrams() {
+        // "martha" and "ricardo" share only "ar"; "marlene" and "martha" share "ma" and "ar".
+        let names = string_vec(vec!["marlene", "martha", "ricardo"]);
+
+        let pairs =
+            NGramPairs::with_min_shared(&names, 2, 2)
+                .collect::<HashSet<IndexPair>>();
+
+        assert_eq!(pairs, [(0usize, 1usize)].iter().copied().collect::<HashSet<IndexPair>>());
+        assert_eq!(NGramPairs::with_min_shared(&names, 2, 1).count(), 3);
+    }
+
+    #[test]
+    fn records_shared_ngram_provenance() {
+        let names = string_vec(vec!["marlene", "martha", "ricardo"]);
+
+        let pairs = NGramPairs::with_provenance(&names, 2, 1);
+        assert_eq!(pairs.pairs_with_provenance(), vec![
+            ((0, 1), string_vec(vec!["ar", "ma"])),
+            ((0, 2), string_vec(vec!["ar"])),
+            ((1, 2), string_vec(vec!["ar"])),
+        ]);
+        assert_eq!(
+            pairs.collect::<Vec<IndexPair>>(),
+            NGramPairs::with_min_shared(&names, 2, 1).collect::<Vec<IndexPair>>(),
+        );
+
+        let pairs = NGramPairs::with_provenance(&names, 2, 2);
+        assert_eq!(pairs.pairs_with_provenance(), vec![((0, 1), string_vec(vec!["ar", "ma"]))]);
+    }
+
+    #[test]
+    fn packs_ngrams() {
+        assert_eq!(packed_ngrams("abc", 2), vec![0x6162, 0x6263]);
+        assert_eq!(packed_ngrams("abcd", 4), vec![0x61626364]);
+        assert!(packed_ngrams("ab", 3).is_empty());
+    }
+
+    #[test]
+    fn packed_pairs_match_string_pairs() {
+        let names =
+            read_file_lines(String::from("data/surnames.txt"), 200).into_iter()
+                .filter(|name| name.is_ascii())
+                .collect::<Vec<String>>();
+
+        for ngram_length in 1..=4 {
+            for min_shared in 1..=2 {
+                let string_pairs = NGramPairs::with_min_shared(&names, ngram_length, min_shared);
+                let packed_pairs = NGramPairs::packed(&names, ngram_length, min_shared);
+
+                assert!(packed_pairs.eq(string_pairs));
+            }
+        }
+    }
+
+    #[test]
+    fn reports_ngram_coverage() {
+        let strings = string_vec(vec!["a", "ab", "abc", "ñu", "abcd"]);
+
+        let coverage = NGramPairs::coverage(&strings, 3);
+        assert_eq!(coverage, NGramCoverage { size: 5, ngram_length: 3, short_strings: 3 });
+        assert_eq!(coverage.short_ratio(), 0.6);
+        assert!(coverage.is_degenerate());
+        assert_eq!(
+            coverage.to_string(),
+            "60% of elements are shorter than ngram_length 3 and produce no grams",
+        );
+
+        assert!(!NGramPairs::coverage(&strings, 2).is_degenerate());
+        assert_eq!(NGramPairs::coverage(&[], 2).short_ratio(), 0.0);
+    }
+}
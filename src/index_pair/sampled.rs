@@ -0,0 +1,122 @@
+//! This module contains an index pair iterator yielding a random sample of all pairs, useful for
+//! cheaply estimating the similarity distribution of an input set before building its full
+//! matrix.
+
+use std::collections::HashSet;
+
+use crate::{Index, Size};
+
+use super::IndexPair;
+
+/// Random sampling strategy for index pair iterator. Pairs are distinct, satisfy `i < j` and are
+/// yielded in ascending `(i, j)` order. The same size, sample count and seed always yield the
+/// same pairs.
+#[derive(Debug)]
+pub struct SampledPairs {
+    /// The sampled index pairs.
+    pairs: Vec<IndexPair>,
+    /// The current iteration index.
+    current_index: Index,
+}
+
+/// Implementation of `SampledPairs`.
+impl SampledPairs {
+    /// Return a new sampling index pair iterator.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The element count for the input set.
+    /// * `sample_count` - The number of pairs to sample. All `size * (size - 1) / 2` pairs are
+    ///   yielded if there are no more than that.
+    /// * `seed` - The seed of the pseudo-random number generator.
+    ///
+    /// # Return
+    ///
+    /// * A new `SampledPairs` instance.
+    pub fn new(size: Size, sample_count: usize, seed: u64) -> SampledPairs {
+        let pair_count = size * size.saturating_sub(1) / 2;
+        let sample_count = sample_count.min(pair_count);
+
+        // Floyd's algorithm: draws exactly `sample_count` distinct ranks, one random number each.
+        let mut random = SplitMix64(seed);
+        let mut ranks = HashSet::with_capacity(sample_count);
+        for bound in pair_count - sample_count..pair_count {
+            let rank = random.below(bound + 1);
+            if !ranks.insert(rank) {
+                ranks.insert(bound);
+            }
+        }
+
+        let mut ranks = ranks.into_iter().collect::<Vec<usize>>();
+        ranks.sort_unstable();
+
+        let mut pairs = Vec::with_capacity(sample_count);
+        let (mut row, mut row_start) = (0, 0);
+        for rank in ranks {
+            while rank >= row_start + (size - row - 1) {
+                row_start += size - row - 1;
+                row += 1;
+            }
+            pairs.push((row, row + 1 + rank - row_start));
+        }
+
+        SampledPairs { pairs, current_index: 0 }
+    }
+}
+
+/// Implement `Iterator<Item = PairIndex>` for `SampledPairs`
+impl Iterator for SampledPairs {
+    type Item = IndexPair;
+
+    /// Return the next index pair.
+    fn next(&mut self) -> Option<IndexPair> {
+        let pair = self.pairs.get(self.current_index).copied();
+        self.current_index += 1;
+        pair
+    }
+}
+
+/// The SplitMix64 pseudo-random number generator: tiny, fast and good enough for sampling.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Return a number in `0..bound`, by multiply-shift range reduction.
+    fn below(&mut self, bound: usize) -> usize {
+        ((u128::from(self.next_u64()) * bound as u128) >> 64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::cartesian::CartesianIndexPairIterator;
+
+    #[test]
+    fn samples_distinct_ordered_pairs() {
+        let pairs = SampledPairs::new(100, 50, 42).collect::<Vec<IndexPair>>();
+
+        assert_eq!(pairs.len(), 50);
+        assert!(pairs.iter().all(|(i, j)| i < j && *j < 100));
+        assert!(pairs.windows(2).all(|window| window[0] < window[1]));
+        assert_eq!(SampledPairs::new(100, 50, 42).collect::<Vec<IndexPair>>(), pairs);
+        assert_ne!(SampledPairs::new(100, 50, 7).collect::<Vec<IndexPair>>(), pairs);
+    }
+
+    #[test]
+    fn yields_all_pairs_when_oversampled() {
+        assert_eq!(
+            SampledPairs::new(5, 1000, 1).collect::<Vec<IndexPair>>(),
+            CartesianIndexPairIterator::new(5).collect::<Vec<IndexPair>>(),
+        );
+        assert_eq!(SampledPairs::new(1, 10, 1).count(), 0);
+        assert_eq!(SampledPairs::new(0, 10, 1).count(), 0);
+    }
+}
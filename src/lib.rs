@@ -7,12 +7,21 @@ pub mod index_pair;
 /// Metrics for measuring similarity.
 pub mod sim_metric;
 
+/// Content-keyed cache for similarity metrics.
+pub mod sim_cache;
+
 /// Similarity matrix.
 pub mod sim_matrix;
 
+/// Neighbor sources for clustering.
+pub mod graph;
+
 /// Clustering algorithm.
 pub mod cluster;
 
+/// Input set paired with its similarity matrix.
+pub mod corpus;
+
 /// Clustering evaluation metrics.
 pub mod evaluation;
 
@@ -26,3 +35,43 @@ pub type Size = usize;
 /// A `usize` index into the input set to be clustered. Elements to be clustered are referred to by
 /// their indices, rather than by their actual content.
 pub type Index = usize;
+
+/// Group approximately duplicate strings. This runs the usual pipeline with sensible defaults:
+/// candidates share a bigram, similarity is the normalized Damerau-Levenshtein ratio and the
+/// resulting matrix is clustered with the default configuration.
+///
+/// # Arguments
+///
+/// * `strings` - The strings to deduplicate.
+/// * `min_similarity` - The minimum similarity for two strings to be considered duplicates.
+///
+/// # Return
+///
+/// The groups of duplicate strings, as indices into `strings`. Strings without duplicates form
+/// singleton groups.
+///
+/// # Example
+///
+/// ```
+/// use grappolo::dedup_strings;
+/// use grappolo::utils::string_vec;
+///
+/// let names = string_vec(vec!["marlene", "malrene", "ricardo", "marlena"]);
+///
+/// assert_eq!(dedup_strings(&names, 0.8), vec![vec![0, 1, 3], vec![2]]);
+/// ```
+pub fn dedup_strings(strings: &[String], min_similarity: sim_metric::Similarity) -> Vec<Vec<Index>> {
+    if strings.is_empty() {
+        return vec![];
+    }
+
+    let metric = sim_metric::damerau_levenshtein_ratio();
+    let similarity_matrix = sim_matrix::SimilarityMatrix::new(
+        strings,
+        min_similarity,
+        &mut index_pair::ngrams::NGramPairs::new(strings, 2),
+        |s1: &String, s2: &String| metric(s1, s2),
+    );
+
+    cluster::Clusterer::cluster_drop_matrix(similarity_matrix)
+}
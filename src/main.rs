@@ -3,8 +3,8 @@ use std::time::SystemTime;
 
 use strsim::normalized_damerau_levenshtein;
 
-use grappolo::{Index, Size};
-use grappolo::cluster::Clusterer;
+use grappolo::Index;
+use grappolo::cluster::{ClusterDisplay, Clusterer};
 use grappolo::index_pair::ngrams::NGramPairs;
 use grappolo::sim_matrix::SimilarityMatrix;
 use grappolo::utils::*;
@@ -25,21 +25,38 @@ fn show_clusters() {
     };
     println!("Names: {}", names.len());
 
+    let ngram_length = 2;
+    let coverage = NGramPairs::coverage(&names, ngram_length);
+    if coverage.short_strings > 0 {
+        println!("{}: {}", if coverage.is_degenerate() { "Warning" } else { "Note" }, coverage);
+    }
+
     let start_time = SystemTime::now();
     let similarity_matrix = SimilarityMatrix::new(
         &names,
         min_similarity,
-        &mut NGramPairs::new(&names, 2),
+        &mut NGramPairs::new(&names, ngram_length),
         |t1, t2| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
     );
     println!(
         "Similarity matrix created in {} seconds",
         millis_since(start_time) as f64 / 1000.0);
+    if let Some(build_stats) = similarity_matrix.build_stats() {
+        println!(
+            "Compared {} of {} pairs ({:.2}% reduction), {} similar",
+            build_stats.compared_pairs,
+            build_stats.cartesian_pairs(),
+            build_stats.reduction_ratio() * 100.0,
+            build_stats.qualifying_pairs);
+    }
+    if similarity_matrix.is_empty() {
+        println!("Warning: no names are {} similar, all clusters will be singletons", min_similarity);
+    }
 
     let mut out = OpenOptions::new()
         .create(true)
         .write(true)
-        .append(false)
+        .truncate(true)
         .open(format!(
             "data/spanish-surnames-matrix-{}.txt",
             min_similarity
@@ -59,9 +76,13 @@ fn show_clusters() {
             .expect("Error writing matrix file");
     }
 
-    println!("Clustering with {} similarity values", &similarity_matrix.similarity_values.len());
+    let threshold_candidates = similarity_matrix.threshold_candidates(20);
+    println!(
+        "Clustering with {} of {} similarity values",
+        threshold_candidates.len(),
+        similarity_matrix.similarity_values.len());
     let indices = (0..names.len()).collect::<Vec<Index>>();
-    for similarity_value in &similarity_matrix.similarity_values {
+    for similarity_value in &threshold_candidates {
         let start_time = SystemTime::now();
 
         let similarity_matrix = similarity_matrix.spin_off(&indices, *similarity_value);
@@ -74,28 +95,16 @@ fn show_clusters() {
             similarity_value,
             millis_since(start_time) as f64 / 1000.0);
 
-        let clustered_count = clustering.clusters.iter()
-            .map(|cluster| cluster.len())
-            .sum::<Size>();
-        assert_eq!(clustered_count, names.len());
+        clustering.validate(names.len())
+            .expect("Clustering is not a partition of the input set");
 
         let mut out = {
             let filename = format!("{}-clusters-{}.txt", base_filename, similarity_value);
             open_output_file(filename)
         };
 
-        for cluster in clustering.clusters {
-            let cluster = &cluster;
-
-            let cluster_names =
-                cluster.iter()
-                    .map(|index| names[*index].clone())
-                    .collect::<Vec<String>>()
-                    .join(",");
-            write!(out,
-                   "{},{}\n",
-                   cluster.len(),
-                   cluster_names)
+        for cluster in &clustering.clusters {
+            writeln!(out, "{}", ClusterDisplay::new(cluster, &names))
                 .expect("Error writing cluster file");
         }
         out.flush()
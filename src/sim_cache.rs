@@ -0,0 +1,383 @@
+//! This module contains a persistent similarity cache keyed by element content rather than by
+//! index, so that cached similarities survive across runs over overlapping datasets.
+//!
+//! Elements are identified only by a 64-bit hash of their content; the elements themselves are
+//! neither stored nor compared. Two distinct elements sharing a hash are therefore
+//! indistinguishable, and the cache silently returns the similarity computed for the other one.
+//! By the birthday bound, the odds of any such collision among `n` distinct elements are about
+//! `n² / 2⁶⁵`: roughly one in 37 million for a million elements. Where even that risk is
+//! unacceptable, compute similarities without the cache.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::Size;
+use crate::sim_metric::Similarity;
+
+/// A cache key: the content hashes of both compared elements, smallest first.
+type CacheKey = (u64, u64);
+
+/// A least-recently-used cache of similarities keyed by element content hashes.
+#[derive(Debug)]
+pub struct SimilarityCache {
+    /// The maximum number of entries held.
+    capacity: Size,
+    /// The cached similarities along with the tick of their last use.
+    entries: HashMap<CacheKey, (Similarity, u64)>,
+    /// Cache keys ordered by the tick of their last use.
+    recency: BTreeMap<u64, CacheKey>,
+    /// The monotonic use counter.
+    tick: u64,
+}
+
+/// Similarity cache implementation.
+impl SimilarityCache {
+    /// Create a new, empty cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - The maximum number of entries to hold. Must be positive.
+    pub fn new(capacity: Size) -> SimilarityCache {
+        assert!(capacity > 0, "Cache capacity must be positive");
+
+        SimilarityCache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    /// Load a cache previously written with `save`. If the file holds more entries than
+    /// `capacity`, only the most recently used ones are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the cache file.
+    /// * `capacity` - The maximum number of entries to hold.
+    ///
+    /// # Return
+    ///
+    /// The loaded cache or the error preventing its loading.
+    pub fn load<P: AsRef<Path>>(path: P, capacity: Size) -> io::Result<SimilarityCache> {
+        let mut cache = SimilarityCache::new(capacity);
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            if fields.len() != 3 {
+                return Err(invalid_data(&line));
+            }
+
+            let hash_1 = fields[0].parse::<u64>().map_err(|_| invalid_data(&line))?;
+            let hash_2 = fields[1].parse::<u64>().map_err(|_| invalid_data(&line))?;
+            let similarity = fields[2].parse::<Similarity>().map_err(|_| invalid_data(&line))?;
+
+            cache.insert((hash_1, hash_2), similarity);
+        }
+
+        Ok(cache)
+    }
+
+    /// Write this cache's entries, least recently used first, so that `load` restores their
+    /// recency order.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the cache file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        for key in self.recency.values() {
+            let (similarity, _) = self.entries[key];
+            writeln!(out, "{} {} {}", key.0, key.1, similarity)?;
+        }
+
+        out.flush()
+    }
+
+    /// Return the number of cached similarities.
+    pub fn len(&self) -> Size {
+        self.entries.len()
+    }
+
+    /// Ascertain whether this cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up a cached similarity, marking it as recently used.
+    fn get(&mut self, key: CacheKey) -> Option<Similarity> {
+        let tick = self.next_tick();
+
+        let (similarity, last_used) = self.entries.get_mut(&key)?;
+        self.recency.remove(last_used);
+        self.recency.insert(tick, key);
+        *last_used = tick;
+
+        Some(*similarity)
+    }
+
+    /// Cache a similarity, evicting the least recently used entry if at capacity.
+    fn insert(&mut self, key: CacheKey, similarity: Similarity) {
+        let tick = self.next_tick();
+
+        if let Some((_, last_used)) = self.entries.insert(key, (similarity, tick)) {
+            self.recency.remove(&last_used);
+        } else if self.entries.len() > self.capacity {
+            let (_, evicted_key) = self.recency.pop_first().unwrap();
+            self.entries.remove(&evicted_key);
+        }
+
+        self.recency.insert(tick, key);
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// A similarity metric wrapper that consults and populates a `SimilarityCache`. The wrapped
+/// metric is assumed symmetric. Elements are told apart by their 64-bit content hash alone, so a
+/// hash collision yields another pair's similarity (see the module documentation for the odds).
+/// Being `Sync`, it can be used from `SimilarityMatrix::new`:
+///
+/// ```
+/// use grappolo::index_pair::cartesian::CartesianIndexPairIterator;
+/// use grappolo::sim_cache::CachedMetric;
+/// use grappolo::sim_matrix::SimilarityMatrix;
+/// use grappolo::utils::string_vec;
+/// use strsim::normalized_damerau_levenshtein;
+///
+/// let names = string_vec(vec!["marlene", "malrene", "marlene"]);
+/// let metric = CachedMetric::new(
+///     |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2),
+///     10_000,
+/// );
+///
+/// let similarity_matrix = SimilarityMatrix::new(
+///     &names,
+///     0.8,
+///     &mut CartesianIndexPairIterator::new(names.len()),
+///     |s1, s2| metric.similarity(s1, s2),
+/// );
+///
+/// assert_eq!(similarity_matrix[0][1], similarity_matrix[2][1]);
+/// assert_eq!(metric.cache_len(), 2);
+/// ```
+pub struct CachedMetric<T: ?Sized, M> {
+    /// The wrapped similarity metric.
+    metric: M,
+    /// The cache of previously computed similarities.
+    cache: Mutex<SimilarityCache>,
+    phantom: PhantomData<fn(&T, &T)>,
+}
+
+/// Cached metric implementation.
+impl<T, M> CachedMetric<T, M>
+    where
+        T: Hash + ?Sized,
+        M: Fn(&T, &T) -> Similarity,
+{
+    /// Wrap a metric with a new, empty cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `metric` - The similarity metric to wrap.
+    /// * `capacity` - The maximum number of similarities to cache.
+    pub fn new(metric: M, capacity: Size) -> CachedMetric<T, M> {
+        CachedMetric::with_cache(metric, SimilarityCache::new(capacity))
+    }
+
+    /// Wrap a metric with an existing (typically loaded) cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `metric` - The similarity metric to wrap.
+    /// * `cache` - The similarity cache to consult and populate.
+    pub fn with_cache(metric: M, cache: SimilarityCache) -> CachedMetric<T, M> {
+        CachedMetric { metric, cache: Mutex::new(cache), phantom: PhantomData }
+    }
+
+    /// Measure the similarity of two elements, computing it only if not already cached.
+    pub fn similarity(&self, t1: &T, t2: &T) -> Similarity {
+        let (hash_1, hash_2) = (content_hash(t1), content_hash(t2));
+        let key = (hash_1.min(hash_2), hash_1.max(hash_2));
+
+        if let Some(similarity) = self.cache.lock().unwrap().get(key) {
+            return similarity;
+        }
+
+        let similarity = (self.metric)(t1, t2);
+        self.cache.lock().unwrap().insert(key, similarity);
+        similarity
+    }
+
+    /// Return the number of cached similarities.
+    pub fn cache_len(&self) -> Size {
+        self.cache.lock().unwrap().len()
+    }
+
+    /// Write the cache to a file; see `SimilarityCache::save`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.cache.lock().unwrap().save(path)
+    }
+
+    /// Unwrap the cache, for example to reuse it with another metric instance.
+    pub fn into_cache(self) -> SimilarityCache {
+        self.cache.into_inner().unwrap()
+    }
+}
+
+/// Hash an element's content with 64-bit FNV-1a. Unlike `std`'s default hasher, FNV-1a isn't
+/// seeded per process, so hashes are stable across runs of the same build. Integers hashed one by
+/// one, including the length prefix of slices, are fed as fixed-width little-endian bytes so that
+/// hashes of strings and string lists also agree across platforms. The bytes otherwise come from
+/// `std`'s `Hash` impls, which promise no stability across Rust releases.
+pub(crate) fn content_hash<T: Hash + ?Sized>(element: &T) -> u64 {
+    let mut hasher = FnvHasher(0xcbf2_9ce4_8422_2325);
+    element.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 64-bit FNV-1a hasher writing multi-byte integers in little-endian order, and `usize` and
+/// `isize` as 64 bits, whatever the platform.
+struct FnvHasher(u64);
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_i64(i as i64);
+    }
+}
+
+fn invalid_data(line: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid cache line: '{}'", line))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use strsim::normalized_damerau_levenshtein;
+
+    use crate::utils::unique_temp_path;
+
+    use super::*;
+
+    #[test]
+    fn hashes_length_prefix_as_little_endian_u64() {
+        let mut hasher = FnvHasher(0xcbf2_9ce4_8422_2325);
+        hasher.write(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        hasher.write(b"ab");
+        hasher.write(&[0xff]);
+
+        assert_eq!(content_hash(&vec![String::from("ab")][..]), hasher.finish());
+    }
+
+    #[test]
+    fn computes_each_content_pair_once() {
+        let calls = AtomicUsize::new(0);
+        let metric = CachedMetric::new(
+            |s1: &str, s2: &str| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                normalized_damerau_levenshtein(s1, s2)
+            },
+            10,
+        );
+
+        let similarity = metric.similarity("marlene", "malrene");
+        assert_eq!(metric.similarity("malrene", "marlene"), similarity);
+        assert_eq!(metric.similarity("marlene", "malrene"), similarity);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        metric.similarity("martha", "marta");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(metric.cache_len(), 2);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = SimilarityCache::new(2);
+
+        cache.insert((1, 2), 0.5);
+        cache.insert((3, 4), 0.6);
+        assert_eq!(cache.get((1, 2)), Some(0.5));
+
+        cache.insert((5, 6), 0.7);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get((3, 4)), None);
+        assert_eq!(cache.get((1, 2)), Some(0.5));
+        assert_eq!(cache.get((5, 6)), Some(0.7));
+    }
+
+    #[test]
+    fn saves_and_loads_cache() {
+        let path = unique_temp_path("similarity-cache.txt");
+
+        let mut cache = SimilarityCache::new(3);
+        cache.insert((1, 2), 0.1 + 0.2);
+        cache.insert((3, 4), 0.6);
+        cache.insert((5, 6), 0.7);
+        cache.get((1, 2));
+        cache.save(&path).unwrap();
+
+        let mut loaded_cache = SimilarityCache::load(&path, 2).unwrap();
+        assert_eq!(loaded_cache.len(), 2);
+        assert_eq!(loaded_cache.get((1, 2)), Some(0.1 + 0.2));
+        assert_eq!(loaded_cache.get((5, 6)), Some(0.7));
+        assert_eq!(loaded_cache.get((3, 4)), None);
+    }
+}
@@ -1,33 +1,214 @@
 //! This module contains the definition of a symmetric similarity matrix.
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, Write};
+use std::num::TryFromIntError;
 use std::ops::Index as BracketedIndex;
+use std::path::Path;
+use std::sync::Mutex;
 
-use itertools::sorted;
 use rayon::iter::ParallelBridge;
 use rayon::prelude::*;
 
 use crate::{Index, Size};
-use crate::index_pair::IndexPair;
+use crate::index_pair::{IndexPair, IndexPairSource};
+use crate::sim_cache::content_hash;
 use crate::sim_metric::Similarity;
 use std::cmp::Ordering;
 
+pub mod lazy;
+pub mod triangular;
+
+/// The largest matrix `SimilarityMatrix::to_dense` accepts. A dense matrix of this size takes
+/// about 3.2GB.
+pub const MAX_DENSE_SIZE: Size = 20_000;
+
 /// Each cell in a row holds a sibling element's index and its similarity to the row's element.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Score {
     pub sibling_index: Index,
     pub similarity: Similarity,
 }
 
+/// A compact `Score` storing its sibling index as `u32` and its similarity as `f32`, halving the
+/// memory taken by each stored score on 64-bit platforms.
+///
+/// The `f32` similarity keeps about 7 significant decimal digits, so distinct `f64` similarities
+/// may collapse into a single value and converting back to `Score` does not restore the original
+/// similarity exactly. Compact scores suit storage of very large graphs; threshold comparisons
+/// against them should allow for this loss of precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompactScore {
+    pub sibling_index: u32,
+    pub similarity: f32,
+}
+
+/// Conversion of a `Score` into a `CompactScore`, failing when the sibling index exceeds
+/// `u32::MAX`.
+impl TryFrom<&Score> for CompactScore {
+    type Error = TryFromIntError;
+
+    fn try_from(score: &Score) -> Result<CompactScore, TryFromIntError> {
+        Ok(CompactScore {
+            sibling_index: u32::try_from(score.sibling_index)?,
+            similarity: score.similarity as f32,
+        })
+    }
+}
+
+/// Conversion of a `CompactScore` back into a `Score`.
+impl From<&CompactScore> for Score {
+    fn from(score: &CompactScore) -> Score {
+        Score {
+            sibling_index: score.sibling_index as Index,
+            similarity: Similarity::from(score.similarity),
+        }
+    }
+}
+
 /// Each row contains similarities for qualifying siblings.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Row {
     pub scores: Vec<Score>
 }
 
+/// Statistics gathered while building a similarity matrix from an index pair iterator, useful
+/// for measuring how much work a blocking strategy saves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildStats {
+    /// The number of elements in the input set.
+    pub size: Size,
+    /// The number of index pairs yielded by the iterator and measured with the metric.
+    pub compared_pairs: usize,
+    /// The number of compared pairs meeting the minimum similarity.
+    pub qualifying_pairs: usize,
+    /// The number of rows holding at least one score. Zero means `min_similarity` left the
+    /// matrix without edges.
+    pub nonzero_rows: Size,
+}
+
+impl BuildStats {
+    /// Return the number of pairs an exhaustive comparison would measure: `n(n-1)/2`.
+    pub fn cartesian_pairs(&self) -> usize {
+        self.size * (self.size - 1) / 2
+    }
+
+    /// Return the fraction of the exhaustive comparisons avoided, between `0.0` (no savings) and
+    /// `1.0`.
+    pub fn reduction_ratio(&self) -> f64 {
+        let cartesian_pairs = self.cartesian_pairs();
+        if cartesian_pairs == 0 {
+            0.0
+        } else {
+            1.0 - self.compared_pairs as f64 / cartesian_pairs as f64
+        }
+    }
+}
+
+/// Policy deriving a symmetric similarity from the two directional scores of an asymmetric
+/// metric. Clustering fundamentally relies on symmetric similarities, so asymmetric metrics must
+/// be symmetrized before their scores are stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symmetrization {
+    /// Keep the larger score: two elements are similar if either direction says so.
+    Max,
+    /// Keep the smaller score: two elements are similar only if both directions say so.
+    Min,
+    /// Average both scores.
+    Mean,
+}
+
+impl Symmetrization {
+    /// Combine the two directional scores of a pair into one.
+    pub fn combine(&self, forward: Similarity, backward: Similarity) -> Similarity {
+        match self {
+            Symmetrization::Max => forward.max(backward),
+            Symmetrization::Min => forward.min(backward),
+            Symmetrization::Mean => (forward + backward) / 2.0,
+        }
+    }
+}
+
+/// How to weigh an element when ranking cluster seeds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RankMode {
+    /// Rank by descending sibling count and, for equal sibling counts, by descending similarity
+    /// sum. This is the default.
+    #[default]
+    ByWeightedDegree,
+    /// Rank by descending mean sibling similarity, breaking ties by descending sibling count. This
+    /// keeps hubs whose many edges are individually weak from seeding loose clusters.
+    ByMeanSimilarity,
+}
+
+/// Errors arising from building a similarity matrix from external data.
+#[derive(Debug)]
+pub enum MatrixError {
+    /// The input could not be read.
+    Io(io::Error),
+    /// An input line is malformed.
+    Parse { line: usize, message: String },
+    /// An input line refers to an index outside the matrix.
+    IndexOutOfRange { line: usize, index: Index, size: Size },
+    /// No pair of elements meets the minimum similarity.
+    NoEdges { min_similarity: Similarity },
+    /// The input holds no elements.
+    Empty,
+    /// A row refers to a sibling outside the matrix.
+    SiblingOutOfRange { row: Index, sibling: Index, size: Size },
+    /// A pair is given different similarities in each direction.
+    Asymmetric { row: Index, column: Index },
+    /// A pair's similarity is not a number.
+    NotANumber { row: Index, column: Index },
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MatrixError::Io(error) =>
+                write!(f, "Error reading matrix input: {}", error),
+            MatrixError::Parse { line, message } =>
+                write!(f, "Error parsing line {}: {}", line, message),
+            MatrixError::IndexOutOfRange { line, index, size } =>
+                write!(f, "Index {} in line {} is out of range for size {}", index, line, size),
+            MatrixError::NoEdges { min_similarity } =>
+                write!(f, "No pair of elements is at least {} similar", min_similarity),
+            MatrixError::Empty =>
+                write!(f, "Cannot create matrix of zero size"),
+            MatrixError::SiblingOutOfRange { row, sibling, size } =>
+                write!(f, "Sibling {} in row {} is out of range for size {}", sibling, row, size),
+            MatrixError::Asymmetric { row, column } =>
+                write!(f, "Pair ({}, {}) has conflicting similarities", row, column),
+            MatrixError::NotANumber { row, column } =>
+                write!(f, "Pair ({}, {}) has a NaN similarity", row, column),
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+impl From<io::Error> for MatrixError {
+    fn from(error: io::Error) -> MatrixError {
+        MatrixError::Io(error)
+    }
+}
+
 /// A simple, sparse similarity matrix. While this matrix has as many rows as elements in the
 /// input set, each row contains scores only for sibling elements whose similarity is above a
 /// given `min_similarity`.
+///
+/// The diagonal is implicitly `1.0` and never stored: no row holds a score for its own element,
+/// even if the similarity metric or the supplied triplets would produce one.
+///
+/// Rows are normally sorted by descending similarity. Matrices created with `new_unsorted` skip
+/// this sort, which is fine for lookups (`matrix[row][column]`) and clustering, as `Row`'s
+/// `ranked_siblings` does its own ranking. Call `sort_rows` before relying on the stored order of
+/// a row's `scores`.
 #[derive(Debug)]
 pub struct SimilarityMatrix {
     /// The collection of rows, each holding zero or more scores consisting of the sibling index
@@ -40,6 +221,13 @@ pub struct SimilarityMatrix {
 
     /// The ordered set of distinct similarity values present in this matrix.
     pub similarity_values: Vec<Similarity>,
+
+    /// Statistics for matrices built from an index pair iterator.
+    build_stats: Option<BuildStats>,
+
+    /// Hash of the elements this matrix was built from, recorded only by `new_fingerprinted` or
+    /// `with_elements_fingerprint`.
+    elements_fingerprint: Option<u64>,
 }
 
 /// similarity matrix implementation.
@@ -54,262 +242,1847 @@ impl SimilarityMatrix {
     /// * `similarity_metric` - The similarity metric to apply for clustering.
     ///
     pub fn new<T, I, M>(
-        elements: &Vec<T>,
+        elements: &[T],
         min_similarity: Similarity,
         index_pair_iterator: &mut I,
         similarity_metric: M,
     ) -> SimilarityMatrix
         where
             T: Sync + Send,
-            I: Iterator<Item=IndexPair> + Send,
+            I: IndexPairSource,
             M: Fn(&T, &T) -> Similarity + Sync,
     {
-        let size = elements.len();
-        assert!(size > 0, "Cannot create matrix from empty vector");
-
-        let mut rows: Vec<Row> = Vec::with_capacity(size);
-        for _ in 0..size {
-            let row: Row = Row { scores: vec![] };
-            rows.push(row);
-        }
-
-        let mut similarity_values = HashSet::new();
-
-        let similarity_triplets =
-            index_pair_iterator
-                .par_bridge()
-                .map(|(row, column)|
-                    (row, column, similarity_metric(&elements[row], &elements[column])))
-                .filter(|(_, _, similarity)| *similarity > 0.0 && *similarity >= min_similarity)
-                .collect::<Vec<(Index, Index, Similarity)>>();
-
-        for (row_index, column_index, similarity) in similarity_triplets {
-            rows[row_index].scores.push(Score { sibling_index: column_index, similarity });
-            rows[column_index].scores.push(Score { sibling_index: row_index, similarity });
-            similarity_values.insert(similarity.to_string());
-        }
+        let mut similarity_matrix =
+            SimilarityMatrix::new_unsorted(elements, min_similarity, index_pair_iterator, similarity_metric);
+        similarity_matrix.sort_rows();
+        similarity_matrix
+    }
 
-        for i in 0..rows.len() {
-            rows[i].scores.sort_by(
-                |Score { sibling_index: _index_1, similarity: similarity_1 },
-                 Score { sibling_index: _index_2, similarity: similarity_2 }|
-                    similarity_2.partial_cmp(&similarity_1).unwrap());
-        }
+    /// Create a new instance of `SimilarityMatrix` as per `new`, recording the fingerprint of the
+    /// elements at build time so that stored copies can later be checked with `verify_against`.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply for clustering.
+    ///
+    /// # Return
+    ///
+    /// The new matrix, carrying the elements' fingerprint.
+    pub fn new_fingerprinted<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> SimilarityMatrix
+        where
+            T: Hash + Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Similarity + Sync,
+    {
+        SimilarityMatrix::new(elements, min_similarity, index_pair_iterator, similarity_metric)
+            .with_elements_fingerprint(elements)
+    }
 
-        let similarity_values = sorted(similarity_values)
-            .map(|similarity| similarity.parse::<Similarity>().unwrap())
-            .collect::<Vec<Similarity>>();
+    /// Create a new instance of `SimilarityMatrix` without sorting its rows by descending
+    /// similarity. Useful when the matrix is only needed for lookups or edge export.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply for clustering.
+    ///
+    pub fn new_unsorted<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> SimilarityMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Similarity + Sync,
+    {
+        SimilarityMatrix::new_unsorted_filtered(
+            elements,
+            min_similarity,
+            index_pair_iterator,
+            |t1, t2| Some(similarity_metric(t1, t2)),
+        )
+    }
 
-        SimilarityMatrix { rows, min_similarity, similarity_values }
+    /// Create a new instance of `SimilarityMatrix` from a metric able to short-circuit: the metric
+    /// returns `None` when it can tell, without computing it in full, that the similarity falls
+    /// below `min_similarity` (for instance, from the length difference of two strings).
+    /// Returning `None` is equivalent to returning a below-threshold score.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The short-circuiting similarity metric to apply for clustering.
+    ///
+    pub fn new_filtered<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> SimilarityMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Option<Similarity> + Sync,
+    {
+        let mut similarity_matrix =
+            SimilarityMatrix::new_unsorted_filtered(elements, min_similarity, index_pair_iterator, similarity_metric);
+        similarity_matrix.sort_rows();
+        similarity_matrix
     }
 
-    /// Return the size of this matrix.
-    pub fn size(&self) -> Size {
-        self.rows.len()
+    /// Create a new instance of `SimilarityMatrix` from a metric trait object, such as one chosen
+    /// at runtime with `sim_metric::metric_by_name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply for clustering.
+    ///
+    /// # Return
+    ///
+    /// The new matrix.
+    pub fn new_dyn<T, I>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: &(dyn Fn(&T, &T) -> Similarity + Sync),
+    ) -> SimilarityMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+    {
+        SimilarityMatrix::new(elements, min_similarity, index_pair_iterator, similarity_metric)
     }
 
-    /// Create a new similarity matrix that is a subset of this matrix.
+    /// Create a new instance of `SimilarityMatrix` from an asymmetric metric, such as "is `a` an
+    /// abbreviation of `b`". Each pair is measured in both directions and the two scores are
+    /// combined into the stored, symmetric similarity as per a symmetrization policy.
     ///
     /// # Arguments
     ///
-    /// * `indices` - Indices to extract from this matrix. Indices in this vector must be less than
-    /// this matrix's `size`.
-    /// * `min_similarity` The minimum similarity used to filter sibling elements in each row.
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum symmetrized score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The asymmetric similarity metric to apply for clustering.
+    /// * `symmetrization` - The policy combining both directional scores.
     ///
     /// # Return
     ///
-    /// A new, boxed similarity matrix.
-    pub fn spin_off(
-        &self,
-        indices: &Vec<Index>,
-        min_similarity: Similarity)
-        -> SimilarityMatrix
+    /// The new matrix.
+    pub fn new_asymmetric<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+        symmetrization: Symmetrization,
+    ) -> SimilarityMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Similarity + Sync,
     {
-        let map =
-            indices
-                .iter()
-                .zip(0..indices.len())
-                .map(|(new_index, old_index)| (*new_index, old_index))
-                .collect::<HashMap<Index, Index>>();
+        SimilarityMatrix::new(
+            elements,
+            min_similarity,
+            index_pair_iterator,
+            |t1, t2| symmetrization.combine(similarity_metric(t1, t2), similarity_metric(t2, t1)),
+        )
+    }
 
-        let index_set = indices.iter().map(|index| *index).collect::<HashSet<Index>>();
+    /// Create a new, unsorted instance of `SimilarityMatrix` from a short-circuiting metric.
+    fn new_unsorted_filtered<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> SimilarityMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Option<Similarity> + Sync,
+    {
+        let size = elements.len();
+        assert!(size > 0, "Cannot create matrix from empty vector");
 
-        let rows =
-            indices
-                .iter()
-                .map(|previous_index| &self.rows[*previous_index])
-                .map(|row|
-                    Row {
-                        scores: row.scores
-                            .iter()
-                            .filter(|score|
-                                index_set.contains(&score.sibling_index) &&
-                                    score.similarity >= min_similarity)
-                            .map(|score|
-                                Score {
-                                    sibling_index: *map.get(&score.sibling_index).unwrap(),
-                                    similarity: score.similarity,
-                                })
-                            .collect::<Vec<Score>>()
-                    }
-                )
-                .collect::<Vec<Row>>();
+        let (similarity_triplets, compared_pairs) =
+            score_pairs(elements, min_similarity, index_pair_iterator, similarity_metric);
 
-        let similarity_values =
-            sorted(
-                rows
-                    .iter()
-                    .flat_map(|row|
-                        row.scores
-                            .iter()
-                            .map(|Score { sibling_index: _, similarity }| similarity.to_string())
-                            .collect::<Vec<String>>()
-                    )
-                    .collect::<HashSet<String>>()
-            )
-                .map(|string| string.parse::<Similarity>().unwrap())
-                .collect::<Vec<Similarity>>();
+        let qualifying_pairs = similarity_triplets.len();
 
-        SimilarityMatrix { rows, min_similarity, similarity_values }
+        let mut similarity_matrix =
+            SimilarityMatrix::from_qualifying_triplets(size, min_similarity, similarity_triplets);
+        similarity_matrix.build_stats = Some(BuildStats {
+            size,
+            compared_pairs,
+            qualifying_pairs,
+            nonzero_rows: similarity_matrix.rows.iter().filter(|row| !row.scores.is_empty()).count(),
+        });
+        similarity_matrix
     }
 
+    /// Create a new instance of `SimilarityMatrix` as per `new`, failing if no pair of elements
+    /// meets the minimum similarity. This guards against a too aggressive threshold silently
+    /// yielding all-singleton clusterings.
     ///
-    pub fn rank_by_weight(&self) -> Vec<Index> {
-        let mut ordered_indices =
-            (0..self.rows.len())
-                .map(|index| (index, &self.rows[index]))
-                .map(|(index, row)| {
-                    let sibling_count = row.scores.len();
-                    let similarity_sum =
-                        row.scores.iter()
-                            .map(|score| score.similarity)
-                            .sum::<Similarity>();
-                    (index, sibling_count, similarity_sum)
-                })
-                .collect::<Vec<(Index, Size, Similarity)>>();
-
-        ordered_indices.sort_by(|(_, sibling_count1, similarity_sum_1), (_, sibling_count2, similarity_sum_2)| {
-            if sibling_count1 > sibling_count2 {
-                Ordering::Less
-            } else if sibling_count1 == sibling_count2 && similarity_sum_1 > similarity_sum_2 {
-                Ordering::Less
-            } else {
-                Ordering::Greater
-            }
-        });
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply for clustering.
+    ///
+    /// # Return
+    ///
+    /// The new matrix or `MatrixError::NoEdges` if it would hold no scores.
+    pub fn try_new<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> Result<SimilarityMatrix, MatrixError>
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Similarity + Sync,
+    {
+        let similarity_matrix =
+            SimilarityMatrix::new(elements, min_similarity, index_pair_iterator, similarity_metric);
 
-        ordered_indices.iter()
-            .map(|(index, _, _)| *index)
-            .collect::<Vec<Index>>()
+        if similarity_matrix.is_empty() {
+            Err(MatrixError::NoEdges { min_similarity })
+        } else {
+            Ok(similarity_matrix)
+        }
     }
-}
-
-/// Implementation of `std::ops::Index` for similarity matrix.
-impl BracketedIndex<Index> for SimilarityMatrix {
-    /// The data type of values returned by the indexing operator (`[]`).
-    type Output = Row;
 
-    /// Return the row at a given position position.
+    /// Create a new instance of `SimilarityMatrix` from a distance function rather than a
+    /// similarity metric. Each distance `d` is stored as the similarity `1.0 - d / max_distance`,
+    /// clamped to `[0.0, 1.0]`; pairs farther apart than `max_distance` are not stored. The bound
+    /// is inclusive: since a zero similarity is indistinguishable from an absent pair, pairs
+    /// exactly `max_distance` apart are stored with the smallest positive similarity instead.
     ///
     /// # Arguments
     ///
-    /// * `index` - The index of the desired row in this matrix.
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `max_distance` - The maximum distance to consider two elements similar. Must be positive.
+    /// * `index_pair_iterator` - The index pair iterator used to measure distance between to elements
+    /// * `distance_function` - The distance function to apply for clustering.
     ///
     /// # Return
     ///
-    /// The row at `index` position.
-    fn index(&self, index: Size) -> &Self::Output {
-        &self.rows[index]
-    }
-}
+    /// The new matrix.
+    pub fn new_from_distance<T, I, D>(
+        elements: &[T],
+        max_distance: f64,
+        index_pair_iterator: &mut I,
+        distance_function: D,
+    ) -> SimilarityMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            D: Fn(&T, &T) -> f64 + Sync,
+    {
+        assert!(max_distance > 0.0, "Maximum distance must be positive");
 
-/// Implementation of `std::ops::Index` for `Score`.
-impl BracketedIndex<Index> for Row {
-    /// The data type of values returned by the indexing operator (`[]`).
-    type Output = Similarity;
+        SimilarityMatrix::new_filtered(
+            elements,
+            0.0,
+            index_pair_iterator,
+            |t1, t2| {
+                let distance = distance_function(t1, t2);
+                if distance > max_distance {
+                    None
+                } else {
+                    Some((1.0 - distance / max_distance).clamp(Similarity::MIN_POSITIVE, 1.0))
+                }
+            },
+        )
+    }
 
-    /// Return the row at a given position position.
+    /// Create a new instance of `SimilarityMatrix` from `(row, column, similarity)` triplets. Each
+    /// pair is stored in both its row and its column, so it need only be given in one direction;
+    /// a pair given more than once, in either direction, keeps its highest similarity. Triplets
+    /// below `min_similarity` or on the diagonal (`row == column`) are ignored.
     ///
     /// # Arguments
     ///
-    /// * `index` - The index of the desired row in this matrix.
+    /// * `size` - The number of elements in the input set.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `similarity_triplets` - The similarity of each pair of elements. Indices must be less
+    ///   than `size` and similarities must not be NaN.
     ///
     /// # Return
     ///
-    /// The row at `index` position.
-    fn index(&self, index: Size) -> &Self::Output {
-        &self.scores
-            .iter()
-            .find(|score| score.sibling_index == index)
-            .map(|score| &score.similarity)
-            .unwrap_or(&0.0)
-    }
-}
+    /// The new matrix.
+    pub fn from_triplets(
+        size: Size,
+        min_similarity: Similarity,
+        similarity_triplets: Vec<(Index, Index, Similarity)>,
+    ) -> SimilarityMatrix {
+        assert!(size > 0, "Cannot create matrix of zero size");
 
-impl Row {
-    pub fn new(scores: Vec<Score>) -> Row {
-        Row { scores }
-    }
+        let mut similarity_triplets =
+            similarity_triplets.into_iter()
+                .inspect(|(row_index, column_index, similarity)| {
+                    assert!(*row_index < size && *column_index < size,
+                            "Triplet ({}, {}) out of range for matrix of size {}", row_index, column_index, size);
+                    assert!(!similarity.is_nan(), "Triplet ({}, {}) has a NaN similarity", row_index, column_index);
+                })
+                .filter(|(_, _, similarity)| *similarity > 0.0 && *similarity >= min_similarity)
+                .filter(|(row_index, column_index, _)| row_index != column_index)
+                .map(|(row_index, column_index, similarity)|
+                    (row_index.min(column_index), row_index.max(column_index), similarity))
+                .collect::<Vec<(Index, Index, Similarity)>>();
 
+        // Duplicate pairs would store a sibling twice in a row, inflating its weight
+        similarity_triplets.sort_unstable_by(|(row_1, column_1, similarity_1), (row_2, column_2, similarity_2)|
+            (row_1, column_1).cmp(&(row_2, column_2)).then(similarity_2.total_cmp(similarity_1)));
+        similarity_triplets.dedup_by_key(|(row_index, column_index, _)| (*row_index, *column_index));
+
+        let mut similarity_matrix =
+            SimilarityMatrix::from_qualifying_triplets(size, min_similarity, similarity_triplets);
+        similarity_matrix.sort_rows();
+        similarity_matrix
+    }
+
+    /// Create a new instance of `SimilarityMatrix` from a file of precomputed neighbor lists, as
+    /// produced by approximate nearest neighbor indices. Each line has the form
+    /// `id: neighbor:score neighbor:score ...`; blank lines are ignored.
+    ///
+    /// Since neighbor relations need not be symmetric, the similarity stored for a pair is the
+    /// maximum of its two directional scores (a pair listed in only one direction keeps that
+    /// score). Self-neighbors are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the neighbor file.
+    /// * `size` - The number of elements in the input set.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    ///
+    /// # Return
+    ///
+    /// The new matrix or the error preventing its creation.
+    pub fn from_neighbor_file<P: AsRef<Path>>(
+        path: P,
+        size: Size,
+        min_similarity: Similarity,
+    ) -> Result<SimilarityMatrix, MatrixError> {
+        assert!(size > 0, "Cannot create matrix of zero size");
+
+        let mut edges: HashMap<IndexPair, Similarity> = HashMap::new();
+
+        for (line_index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let parse_index = |string: &str| -> Result<Index, MatrixError> {
+                let index = string.trim().parse::<Index>()
+                    .map_err(|error| MatrixError::Parse {
+                        line: line_number,
+                        message: format!("Invalid index '{}': {}", string, error),
+                    })?;
+                if index < size {
+                    Ok(index)
+                } else {
+                    Err(MatrixError::IndexOutOfRange { line: line_number, index, size })
+                }
+            };
+
+            let (id, neighbors) = line.split_once(':')
+                .ok_or_else(|| MatrixError::Parse {
+                    line: line_number,
+                    message: String::from("Missing ':' after element id"),
+                })?;
+            let row_index = parse_index(id)?;
+
+            for neighbor in neighbors.split_whitespace() {
+                let (sibling, similarity) = neighbor.split_once(':')
+                    .ok_or_else(|| MatrixError::Parse {
+                        line: line_number,
+                        message: format!("Neighbor '{}' is not of the form neighbor:score", neighbor),
+                    })?;
+                let sibling_index = parse_index(sibling)?;
+                let similarity = similarity.parse::<Similarity>()
+                    .map_err(|error| MatrixError::Parse {
+                        line: line_number,
+                        message: format!("Invalid score '{}': {}", similarity, error),
+                    })?;
+                if similarity.is_nan() {
+                    return Err(MatrixError::NotANumber { row: row_index, column: sibling_index });
+                }
+
+                if sibling_index != row_index {
+                    let pair = (row_index.min(sibling_index), row_index.max(sibling_index));
+                    let stored = edges.entry(pair).or_insert(similarity);
+                    *stored = stored.max(similarity);
+                }
+            }
+        }
+
+        let similarity_triplets =
+            edges.into_iter()
+                .filter(|(_, similarity)| *similarity > 0.0 && *similarity >= min_similarity)
+                .map(|((row, column), similarity)| (row, column, similarity))
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        let mut similarity_matrix =
+            SimilarityMatrix::from_qualifying_triplets(size, min_similarity, similarity_triplets);
+        similarity_matrix.sort_rows();
+        Ok(similarity_matrix)
+    }
+
+    /// Combine two independently built matrices into one whose elements are `a`'s followed by
+    /// `b`'s, so that `b`'s indices are offset by `a.size()`. Intra-batch edges are preserved and
+    /// cross-batch edges are added symmetrically.
+    ///
+    /// The combined matrix uses the stricter (higher) of both minimum similarities, as pairs below
+    /// it weren't stored by the stricter matrix. Edges below it, whether intra-batch edges of the
+    /// laxer matrix or cross-batch edges, are dropped so the whole matrix honors it.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - The first matrix.
+    /// * `b` - The second matrix.
+    /// * `cross_edges` - The similarity of pairs `(a_index, b_index)`, where `b_index` is not
+    ///   offset.
+    ///
+    /// # Return
+    ///
+    /// The combined matrix.
+    pub fn concat(
+        a: SimilarityMatrix,
+        b: SimilarityMatrix,
+        cross_edges: Vec<(Index, Index, Similarity)>,
+    ) -> SimilarityMatrix {
+        let offset = a.size();
+        let size = offset + b.size();
+        let min_similarity = a.min_similarity.max(b.min_similarity);
+
+        let intra_edges = |matrix: SimilarityMatrix, offset: Index| {
+            matrix.rows.into_iter()
+                .enumerate()
+                .flat_map(move |(index, row)|
+                    row.scores.into_iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .map(move |score| (index + offset, score.sibling_index + offset, score.similarity)))
+        };
+
+        let similarity_triplets =
+            intra_edges(a, 0)
+                .chain(intra_edges(b, offset))
+                .chain(cross_edges.into_iter()
+                    .map(|(a_index, b_index, similarity)| {
+                        assert!(a_index < offset && b_index + offset < size,
+                                "Cross edge ({}, {}) out of range", a_index, b_index);
+                        (a_index, b_index + offset, similarity)
+                    }))
+                .filter(|(_, _, similarity)| *similarity > 0.0 && *similarity >= min_similarity)
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        let mut similarity_matrix =
+            SimilarityMatrix::from_qualifying_triplets(size, min_similarity, similarity_triplets);
+        similarity_matrix.sort_rows();
+        similarity_matrix
+    }
+
+    /// Create a new, unsorted instance of `SimilarityMatrix` from triplets already known to meet
+    /// the minimum similarity and to hold each pair once. Each triplet is stored in both its row
+    /// and its column, save for diagonal triplets, which are dropped.
+    fn from_qualifying_triplets(
+        size: Size,
+        min_similarity: Similarity,
+        similarity_triplets: Vec<(Index, Index, Similarity)>,
+    ) -> SimilarityMatrix {
+        // Size each row up front so high-degree rows don't reallocate repeatedly
+        let mut degrees = vec![0usize; size];
+        for (row_index, column_index, _) in &similarity_triplets {
+            if row_index != column_index {
+                degrees[*row_index] += 1;
+                degrees[*column_index] += 1;
+            }
+        }
+        let mut rows =
+            degrees.into_iter()
+                .map(|degree| Row { scores: Vec::with_capacity(degree) })
+                .collect::<Vec<Row>>();
+
+        for (row_index, column_index, similarity) in similarity_triplets {
+            if row_index == column_index {
+                continue;
+            }
+            rows[row_index].scores.push(Score { sibling_index: column_index, similarity });
+            rows[column_index].scores.push(Score { sibling_index: row_index, similarity });
+        }
+
+        let similarity_values = distinct_similarities(&rows);
+
+        SimilarityMatrix {
+            rows,
+            min_similarity,
+            similarity_values,
+            build_stats: None,
+            elements_fingerprint: None,
+        }
+    }
+
+    /// Sort each row's scores by descending similarity. Rows already in order are left as they
+    /// are at linear cost.
+    pub fn sort_rows(&mut self) {
+        for row in self.rows.iter_mut().filter(|row| !row.is_sorted()) {
+            row.scores.sort_by(
+                |Score { sibling_index: _index_1, similarity: similarity_1 },
+                 Score { sibling_index: _index_2, similarity: similarity_2 }|
+                    similarity_2.partial_cmp(similarity_1).unwrap());
+        }
+    }
+
+    /// Recompute `similarity_values` from the current rows. Call this after editing `rows`
+    /// directly (pruning or adding scores), which otherwise leaves `similarity_values` stale.
+    pub fn rebuild_similarity_values(&mut self) {
+        self.similarity_values = distinct_similarities(&self.rows);
+    }
+
+    /// Ascertain whether each row's scores are sorted by descending similarity. Rows are
+    /// inspected on each call, so the answer stays accurate after `rows` is edited directly.
+    pub fn has_sorted_rows(&self) -> bool {
+        self.rows.iter().all(Row::is_sorted)
+    }
+
+    /// Return the size of this matrix.
+    pub fn size(&self) -> Size {
+        self.rows.len()
+    }
+
+    /// Ascertain whether this matrix holds no edges at all, i.e. no element has a sibling. Note
+    /// this says nothing about `size`, which is always positive.
+    pub fn is_empty(&self) -> bool {
+        self.rows.iter().all(|row| row.scores.is_empty())
+    }
+
+    /// Return the statistics gathered while building this matrix. Only matrices built from an
+    /// index pair iterator (by `new` and `new_unsorted`) carry statistics.
+    pub fn build_stats(&self) -> Option<&BuildStats> {
+        self.build_stats.as_ref()
+    }
+
+    /// Record the fingerprint of the elements this matrix was built from, so that a cached or
+    /// deserialized copy can later be checked with `verify_against` before being clustered.
+    /// Fingerprints are opt-in: constructors other than `new_fingerprinted` don't hash the
+    /// elements, so matrices built otherwise must record it through this method.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The elements this matrix was built from.
+    ///
+    /// # Return
+    ///
+    /// This matrix, carrying the elements' fingerprint.
+    pub fn with_elements_fingerprint<T: Hash>(mut self, elements: &[T]) -> SimilarityMatrix {
+        self.elements_fingerprint = Some(content_hash(elements));
+        self
+    }
+
+    /// Restore a previously recorded elements fingerprint, as when loading a stored matrix.
+    pub fn set_elements_fingerprint(&mut self, fingerprint: u64) {
+        self.elements_fingerprint = Some(fingerprint);
+    }
+
+    /// Return the fingerprint of the elements this matrix was built from, if recorded. For string
+    /// elements the fingerprint agrees across runs and platforms and can thus be stored alongside
+    /// the matrix, though it may change with the Rust release the crate is built with.
+    pub fn elements_fingerprint(&self) -> Option<u64> {
+        self.elements_fingerprint
+    }
+
+    /// Ascertain whether this matrix was built from a given element list, guarding against
+    /// clustering with stale edges after the input changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The current elements, in order.
+    ///
+    /// # Return
+    ///
+    /// Whether the elements match the recorded fingerprint. Matrices without a fingerprint
+    /// can't be verified and always fail.
+    pub fn verify_against<T: Hash>(&self, elements: &[T]) -> bool {
+        self.elements_fingerprint == Some(content_hash(elements))
+    }
+
+    /// Return the minimum similarity used for creating this matrix.
+    pub fn min_similarity(&self) -> Similarity {
+        self.min_similarity
+    }
+
+    /// Create a new similarity matrix that is a subset of this matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - Indices to extract from this matrix. Indices in this vector must be less than
+    ///   this matrix's `size`.
+    /// * `min_similarity` The minimum similarity used to filter sibling elements in each row.
+    ///
+    /// # Return
+    ///
+    /// A new, boxed similarity matrix. Row order, sorted or not, is preserved.
+    pub fn spin_off(
+        &self,
+        indices: &[Index],
+        min_similarity: Similarity)
+        -> SimilarityMatrix
+    {
+        let map =
+            indices
+                .iter()
+                .enumerate()
+                .map(|(old_index, new_index)| (*new_index, old_index))
+                .collect::<HashMap<Index, Index>>();
+
+        let index_set = indices.iter().copied().collect::<HashSet<Index>>();
+
+        let rows =
+            indices
+                .iter()
+                .map(|previous_index| &self.rows[*previous_index])
+                .map(|row|
+                    Row {
+                        scores: row.scores
+                            .iter()
+                            .filter(|score|
+                                index_set.contains(&score.sibling_index) &&
+                                    score.similarity >= min_similarity)
+                            .map(|score|
+                                Score {
+                                    sibling_index: *map.get(&score.sibling_index).unwrap(),
+                                    similarity: score.similarity,
+                                })
+                            .collect::<Vec<Score>>()
+                    }
+                )
+                .collect::<Vec<Row>>();
+
+        let similarity_values = distinct_similarities(&rows);
+
+        SimilarityMatrix {
+            rows,
+            min_similarity,
+            similarity_values,
+            build_stats: None,
+            elements_fingerprint: None,
+        }
+    }
+
+    /// Create a new similarity matrix that is a subset of this matrix, additionally excluding
+    /// weakly connected elements. An element's weight is the sum of its similarities to the other
+    /// `indices` elements that pass `min_similarity`, so edges dropped by `min_similarity` never
+    /// count towards it. Elements weighing less than `min_node_weight` are excluded entirely and
+    /// are meant to become singletons at the parent level.
+    ///
+    /// # Arguments
+    ///
+    /// * `indices` - Array of indices to be used to create a spin-off matrix.
+    /// * `min_similarity` The minimum similarity used to filter sibling elements in each row.
+    /// * `min_node_weight` The minimum weight for an element to be retained, if any.
+    ///
+    /// # Return
+    ///
+    /// A new similarity matrix and the retained elements of `indices`, in matrix order.
+    pub fn spin_off_filtered(
+        &self,
+        indices: &[Index],
+        min_similarity: Similarity,
+        min_node_weight: Option<Similarity>,
+    ) -> (SimilarityMatrix, Vec<Index>) {
+        let retained = match min_node_weight {
+            None => indices.to_vec(),
+            Some(min_node_weight) => {
+                let index_set = indices.iter().copied().collect::<HashSet<Index>>();
+                indices.iter()
+                    .copied()
+                    .filter(|index| {
+                        let node_weight =
+                            self.rows[*index].scores.iter()
+                                .filter(|score|
+                                    index_set.contains(&score.sibling_index) &&
+                                        score.similarity >= min_similarity)
+                                .map(|score| score.similarity)
+                                .sum::<Similarity>();
+                        node_weight >= min_node_weight
+                    })
+                    .collect::<Vec<Index>>()
+            }
+        };
+
+        (self.spin_off(&retained, min_similarity), retained)
+    }
+
+    /// Create a new similarity matrix that is a subset of this matrix, retaining the elements
+    /// flagged in a mask.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - Mask flagging the elements to retain. Its length must equal this matrix's
+    ///   `size`.
+    /// * `min_similarity` The minimum similarity used to filter sibling elements in each row.
+    ///
+    /// # Return
+    ///
+    /// A new similarity matrix whose indices follow the order of the retained elements.
+    pub fn spin_off_mask(&self, keep: &[bool], min_similarity: Similarity) -> SimilarityMatrix {
+        assert_eq!(keep.len(), self.size(), "Mask length must equal matrix size");
+
+        let indices =
+            keep.iter()
+                .enumerate()
+                .filter(|(_, keep)| **keep)
+                .map(|(index, _)| index)
+                .collect::<Vec<Index>>();
+
+        self.spin_off(&indices, min_similarity)
+    }
+
+    /// Select up to `n` quantile-spaced threshold candidates from this matrix's distinct
+    /// similarity values. The lowest and highest values are always included when `n > 1`.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The desired number of candidates.
+    ///
+    /// # Return
+    ///
+    /// The ascending candidate thresholds. All similarity values are returned if there are `n`
+    /// or fewer of them.
+    pub fn threshold_candidates(&self, n: usize) -> Vec<Similarity> {
+        let values = &self.similarity_values;
+
+        if n == 0 || values.is_empty() {
+            vec![]
+        } else if n >= values.len() {
+            values.clone()
+        } else if n == 1 {
+            vec![values[values.len() / 2]]
+        } else {
+            let last = (values.len() - 1) as f64;
+            (0..n)
+                .map(|i| {
+                    let position = (i as f64 * last / (n - 1) as f64).round() as usize;
+                    values[position]
+                })
+                .collect::<Vec<Similarity>>()
+        }
+    }
+
+    /// Bucket the similarities of this matrix's edges into equal-width bins over
+    /// `[min_similarity, 1.0]`, each undirected edge counted once. The gap between the buckets
+    /// of matches and non-matches is where a good threshold sits.
+    ///
+    /// # Arguments
+    ///
+    /// * `bins` - The number of buckets. Must be positive.
+    ///
+    /// # Return
+    ///
+    /// Each bucket's start and edge count, in ascending order. Similarities of exactly `1.0`
+    /// fall into the last bucket.
+    pub fn similarity_histogram(&self, bins: usize) -> Vec<(Similarity, usize)> {
+        assert!(bins > 0, "Histogram must have at least one bin");
+
+        let width = (1.0 - self.min_similarity) / bins as f64;
+        let mut counts = vec![0; bins];
+        for (index, row) in self.rows.iter().enumerate() {
+            for score in row.scores.iter().filter(|score| score.sibling_index > index) {
+                let bucket = if width > 0.0 {
+                    ((score.similarity - self.min_similarity) / width).max(0.0) as usize
+                } else {
+                    0
+                };
+                counts[bucket.min(bins - 1)] += 1;
+            }
+        }
+
+        counts.into_iter().enumerate()
+            .map(|(bucket, count)| (self.min_similarity + bucket as f64 * width, count))
+            .collect::<Vec<(Similarity, usize)>>()
+    }
+
+    /// Compute a percentile of the similarities of this matrix's edges, each undirected edge
+    /// counted once, as a direct aid in picking a threshold.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - The percentile, between `0.0` and `100.0`.
+    ///
+    /// # Return
+    ///
+    /// The similarity below which `p` percent of the edges fall, linearly interpolated between
+    /// neighboring edges, or `min_similarity` for a matrix without edges.
+    pub fn similarity_percentile(&self, p: f64) -> Similarity {
+        assert!((0.0..=100.0).contains(&p), "Percentile must be between 0.0 and 100.0");
+
+        let mut similarities =
+            self.rows.iter().enumerate()
+                .flat_map(|(index, row)| {
+                    row.scores.iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .map(|score| score.similarity)
+                })
+                .collect::<Vec<Similarity>>();
+        if similarities.is_empty() {
+            return self.min_similarity;
+        }
+        similarities.sort_by(|similarity_1, similarity_2| similarity_1.partial_cmp(similarity_2).unwrap());
+
+        let rank = p / 100.0 * (similarities.len() - 1) as f64;
+        let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+        similarities[below] + (similarities[above] - similarities[below]) * (rank - below as f64)
+    }
+
+    /// List this matrix's edges in one globally sorted sequence, each undirected edge once as
+    /// `(i, j, similarity)` with `i < j`. This is the input of Kruskal-style single linkage and
+    /// of threshold stepping. The whole edge list is allocated at once, which for matrices with
+    /// hundreds of millions of edges takes gigabytes.
+    ///
+    /// # Arguments
+    ///
+    /// * `descending` - Whether to sort by descending rather than ascending similarity.
+    ///
+    /// # Return
+    ///
+    /// The sorted edges. Equally similar edges are ordered by ascending index pair and NaN
+    /// similarities, which a well-behaved metric never yields, sort as greater than any number.
+    pub fn sorted_edges(&self, descending: bool) -> Vec<(Index, Index, Similarity)> {
+        let mut edges =
+            self.rows.iter().enumerate()
+                .flat_map(|(index, row)| {
+                    row.scores.iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .map(move |score| (index, score.sibling_index, score.similarity))
+                })
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        edges.sort_by(|(row_1, column_1, similarity_1), (row_2, column_2, similarity_2)| {
+            let by_similarity = similarity_1.total_cmp(similarity_2);
+            let by_similarity = if descending { by_similarity.reverse() } else { by_similarity };
+            by_similarity.then((row_1, column_1).cmp(&(row_2, column_2)))
+        });
+        edges
+    }
+
+    /// Snap an arbitrary threshold to the closest similarity value present in this matrix.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity` - The threshold to snap.
+    ///
+    /// # Return
+    ///
+    /// The closest similarity value or, if this matrix holds no values, `similarity` itself.
+    pub fn nearest_value(&self, similarity: Similarity) -> Similarity {
+        let values = &self.similarity_values;
+
+        if values.is_empty() {
+            return similarity;
+        }
+
+        let position = values.partition_point(|value| *value < similarity);
+        if position == 0 {
+            values[0]
+        } else if position == values.len() {
+            values[position - 1]
+        } else {
+            let (below, above) = (values[position - 1], values[position]);
+            if similarity - below <= above - similarity { below } else { above }
+        }
+    }
+
+    /// List the most similar siblings of an element, turning the matrix into a nearest-neighbor
+    /// lookup structure that needs no clustering.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The element whose neighbors are sought. Must be less than this matrix's `size`.
+    /// * `n` - The maximum number of neighbors to return.
+    ///
+    /// # Return
+    ///
+    /// Up to `n` sibling indices paired with their similarities, most similar first.
+    pub fn top_neighbors(&self, index: Index, n: usize) -> Vec<(Index, Similarity)> {
+        assert!(index < self.size(), "Index {} out of range for matrix of size {}", index, self.size());
+
+        let row = &self.rows[index];
+        if row.is_sorted() {
+            row.scores.iter()
+                .take(n)
+                .map(|score| (score.sibling_index, score.similarity))
+                .collect::<Vec<(Index, Similarity)>>()
+        } else {
+            row.ranked_siblings(&HashSet::new()).into_iter()
+                .take(n)
+                .map(|sibling_index| (sibling_index, row[sibling_index]))
+                .collect::<Vec<(Index, Similarity)>>()
+        }
+    }
+
+    /// Find, in parallel, the most similar corpus elements of each of a batch of query elements,
+    /// turning this matrix's corpus into a search index that needs no rebuilding. Only
+    /// similarities meeting this matrix's `min_similarity` are returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `queries` - The elements to look up.
+    /// * `corpus` - The input set this matrix was built from.
+    /// * `similarity_metric` - The similarity metric to apply.
+    /// * `top_k` - The maximum number of neighbors to return per query.
+    ///
+    /// # Return
+    ///
+    /// For each query, up to `top_k` corpus indices paired with their similarities, most similar
+    /// first and equally similar ones by ascending index.
+    pub fn batch_query<T, M>(
+        &self,
+        queries: &[T],
+        corpus: &[T],
+        similarity_metric: M,
+        top_k: usize,
+    ) -> Vec<Vec<(Index, Similarity)>>
+        where
+            T: Sync,
+            M: Fn(&T, &T) -> Similarity + Sync,
+    {
+        self.batch_query_blocked(queries, corpus, similarity_metric, top_k, |_| (0..corpus.len()).collect())
+    }
+
+    /// Find the most similar corpus elements of each query as per `batch_query`, comparing each
+    /// query only against the candidates yielded by a blocking function.
+    ///
+    /// # Arguments
+    ///
+    /// * `queries` - The elements to look up.
+    /// * `corpus` - The input set this matrix was built from.
+    /// * `similarity_metric` - The similarity metric to apply.
+    /// * `top_k` - The maximum number of neighbors to return per query.
+    /// * `candidates` - The function returning the corpus indices to compare a query against.
+    ///
+    /// # Return
+    ///
+    /// For each query, up to `top_k` corpus indices paired with their similarities, most similar
+    /// first and equally similar ones by ascending index.
+    pub fn batch_query_blocked<T, M, C>(
+        &self,
+        queries: &[T],
+        corpus: &[T],
+        similarity_metric: M,
+        top_k: usize,
+        candidates: C,
+    ) -> Vec<Vec<(Index, Similarity)>>
+        where
+            T: Sync,
+            M: Fn(&T, &T) -> Similarity + Sync,
+            C: Fn(&T) -> Vec<Index> + Sync,
+    {
+        assert_eq!(corpus.len(), self.size(), "Corpus length must equal matrix size");
+
+        queries.par_iter()
+            .map(|query| {
+                let mut candidate_indices = candidates(query);
+                candidate_indices.sort_unstable();
+                candidate_indices.dedup();
+
+                let mut neighbors =
+                    candidate_indices.into_iter()
+                        .map(|index| (index, similarity_metric(query, &corpus[index])))
+                        .filter(|(_, similarity)| *similarity > 0.0 && *similarity >= self.min_similarity)
+                        .collect::<Vec<(Index, Similarity)>>();
+                neighbors.sort_by(|(index_1, similarity_1), (index_2, similarity_2)| {
+                    similarity_2.partial_cmp(similarity_1).unwrap().then(index_1.cmp(index_2))
+                });
+                neighbors.truncate(top_k);
+                neighbors
+            })
+            .collect::<Vec<Vec<(Index, Similarity)>>>()
+    }
+
+    /// Recompute the similarity between two elements from the elements themselves. Unlike
+    /// `matrix[i][j]`, which yields `0.0` for pairs not stored because they fall below
+    /// `min_similarity` (or were never compared), this returns the metric's actual value.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The index of the first element.
+    /// * `j` - The index of the second element.
+    /// * `elements` - The input set this matrix was built from.
+    /// * `similarity_metric` - The similarity metric this matrix was built with.
+    ///
+    /// # Return
+    ///
+    /// The similarity between both elements.
+    pub fn recompute_similarity<T, M>(&self, i: Index, j: Index, elements: &[T], similarity_metric: M) -> Similarity
+        where M: Fn(&T, &T) -> Similarity,
+    {
+        assert_eq!(elements.len(), self.size(), "Element count must equal matrix size");
+
+        similarity_metric(&elements[i], &elements[j])
+    }
+
+    /// Write this matrix as an undirected GraphML graph, inspectable in tools such as yEd or
+    /// Cytoscape. Each edge is written once and carries its similarity as a `weight` attribute.
+    ///
+    /// # Arguments
+    ///
+    /// * `w` - The writer to emit GraphML to.
+    /// * `labels` - Optional node labels, one per element, emitted as a `label` attribute.
+    ///
+    /// # Return
+    ///
+    /// The result of writing to `w`.
+    pub fn write_graphml<W: Write>(&self, w: &mut W, labels: Option<&[String]>) -> io::Result<()> {
+        if let Some(labels) = labels {
+            assert_eq!(labels.len(), self.size(), "Label count must equal matrix size");
+        }
+
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(w, r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#)?;
+        writeln!(w, r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+        writeln!(w, r#"  <graph id="G" edgedefault="undirected">"#)?;
+
+        for index in 0..self.size() {
+            match labels {
+                Some(labels) => {
+                    writeln!(w, r#"    <node id="n{}">"#, index)?;
+                    writeln!(w, r#"      <data key="label">{}</data>"#, xml_escape(&labels[index]))?;
+                    writeln!(w, "    </node>")?;
+                }
+                None => writeln!(w, r#"    <node id="n{}"/>"#, index)?,
+            }
+        }
+
+        for (index, row) in self.rows.iter().enumerate() {
+            for score in row.scores.iter().filter(|score| score.sibling_index > index) {
+                writeln!(w, r#"    <edge source="n{}" target="n{}">"#, index, score.sibling_index)?;
+                writeln!(w, r#"      <data key="weight">{}</data>"#, score.similarity)?;
+                writeln!(w, "    </edge>")?;
+            }
+        }
+
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")
+    }
+
+    /// Expand this matrix into a dense `size` x `size` matrix, as expected by dense-matrix tools
+    /// such as dendrogram libraries. Stored similarities are filled in, the diagonal holds `1.0`
+    /// and every other cell holds `0.0`, including pairs below `min_similarity`.
+    ///
+    /// This takes O(n²) memory, so it's only meant for small inputs: it panics for matrices
+    /// larger than `MAX_DENSE_SIZE`.
+    ///
+    /// # Return
+    ///
+    /// The dense matrix, as a vector of rows.
+    pub fn to_dense(&self) -> Vec<Vec<Similarity>> {
+        let size = self.size();
+        assert!(size <= MAX_DENSE_SIZE, "Matrix too large to densify: {} > {}", size, MAX_DENSE_SIZE);
+
+        self.rows.iter().enumerate()
+            .map(|(index, row)| {
+                let mut dense_row = vec![0.0; size];
+                dense_row[index] = 1.0;
+                for score in &row.scores {
+                    dense_row[score.sibling_index] = score.similarity;
+                }
+                dense_row
+            })
+            .collect()
+    }
+
+    /// Rank this matrix's indices by weight, as used to pick cluster seeds.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - How to weigh each row.
+    ///
+    /// # Return
+    ///
+    /// The indices, heaviest first.
+    pub fn rank_by_weight(&self, mode: RankMode) -> Vec<Index> {
+        rank_weights(
+            mode,
+            self.rows.iter()
+                .enumerate()
+                .map(|(index, row)| {
+                    let (sibling_count, similarity_sum) = row.weight();
+                    (index, sibling_count, similarity_sum)
+                })
+                .collect::<Vec<(Index, Size, Similarity)>>())
+    }
+}
+
+/// Order indices by descending weight, as per a ranking mode.
+///
+/// # Arguments
+///
+/// * `mode` - How to weigh each index.
+/// * `weights` - Each index paired with its sibling count and similarity sum.
+///
+/// # Return
+///
+/// The ordered indices.
+pub(crate) fn rank_weights(mode: RankMode, mut weights: Vec<(Index, Size, Similarity)>) -> Vec<Index> {
+    if mode == RankMode::ByMeanSimilarity {
+        let mean = |sibling_count: Size, similarity_sum: Similarity|
+            if sibling_count == 0 { 0.0 } else { similarity_sum / sibling_count as Similarity };
+
+        weights.sort_by(|(index_1, sibling_count_1, similarity_sum_1), (index_2, sibling_count_2, similarity_sum_2)|
+            mean(*sibling_count_2, *similarity_sum_2).partial_cmp(&mean(*sibling_count_1, *similarity_sum_1))
+                .unwrap_or(Ordering::Equal)
+                .then(sibling_count_2.cmp(sibling_count_1))
+                .then(index_1.cmp(index_2)));
+
+        return weights.iter()
+            .map(|(index, _, _)| *index)
+            .collect::<Vec<Index>>();
+    }
+
+    weights.sort_by(|(index_1, sibling_count_1, similarity_sum_1), (index_2, sibling_count_2, similarity_sum_2)|
+        sibling_count_2.cmp(sibling_count_1)
+            .then(similarity_sum_2.total_cmp(similarity_sum_1))
+            .then(index_1.cmp(index_2)));
+
+    weights.iter()
+        .map(|(index, _, _)| *index)
+        .collect::<Vec<Index>>()
+}
+
+/// Escape the characters XML reserves in text and attribute values.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Collect the ascending set of distinct similarity values present in a set of rows.
+///
+/// Similarities are keyed on their bit pattern, which preserves numeric ordering for the
+/// non-negative values stored in a matrix and dedups identical floats exactly.
+///
+/// # Arguments
+///
+/// * `rows` - The rows whose scores are to be collected.
+///
+/// # Return
+///
+/// The ordered set of distinct similarity values.
+fn distinct_similarities(rows: &[Row]) -> Vec<Similarity> {
+    rows.iter()
+        .flat_map(|row| row.scores.iter().map(|score| score.similarity.to_bits()))
+        .collect::<BTreeSet<u64>>()
+        .into_iter()
+        .map(Similarity::from_bits)
+        .collect::<Vec<Similarity>>()
+}
+
+/// Measure the similarity of each index pair in parallel and hand each qualifying triplet to a
+/// sink instead of collecting it, so that pipelines too large to hold a matrix in memory can
+/// stream triplets straight to external storage. Self pairs are skipped.
+///
+/// Scoring runs in parallel but the sink is called under a lock, one triplet at a time and in no
+/// particular order.
+///
+/// # Arguments
+///
+/// * `elements` - The input set vector containing elements to be compared.
+/// * `min_similarity` - The minimum score to consider two elements similar.
+/// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+/// * `similarity_metric` - The similarity metric to apply.
+/// * `sink` - The consumer of each qualifying `(row, column, similarity)` triplet.
+///
+/// # Return
+///
+/// The number of pairs compared.
+///
+/// # Example
+///
+/// ```
+/// use grappolo::index_pair::cartesian::CartesianIndexPairIterator;
+/// use grappolo::sim_matrix::compute_triplets;
+///
+/// let numbers = vec![1.0, 1.1, 5.0];
+/// let mut triplets = Vec::new();
+/// let compared_pairs = compute_triplets(
+///     &numbers,
+///     0.5,
+///     &mut CartesianIndexPairIterator::new(numbers.len()),
+///     |a: &f64, b: &f64| 1.0 / (1.0 + (a - b).abs()),
+///     |row, column, similarity| triplets.push((row, column, similarity)),
+/// );
+///
+/// assert_eq!(compared_pairs, 3);
+/// assert_eq!(triplets.len(), 1);
+/// ```
+pub fn compute_triplets<T, I, M, F>(
+    elements: &[T],
+    min_similarity: Similarity,
+    index_pair_iterator: &mut I,
+    similarity_metric: M,
+    sink: F,
+) -> usize
+    where
+        T: Sync + Send,
+        I: IndexPairSource,
+        M: Fn(&T, &T) -> Similarity + Sync,
+        F: FnMut(Index, Index, Similarity) + Send,
+{
+    let mut compared_pairs = 0;
+    let sink = Mutex::new(sink);
+    let optional_metric = |t1: &T, t2: &T| Some(similarity_metric(t1, t2));
+
+    qualifying_pairs(
+        elements,
+        min_similarity,
+        index_pair_iterator,
+        &optional_metric,
+        &mut compared_pairs,
+    )
+        .for_each(|(row, column, similarity)| (sink.lock().unwrap())(row, column, similarity));
+
+    compared_pairs
+}
+
+/// Measure the similarity of each index pair in parallel, keeping the pairs meeting a minimum
+/// similarity. Self pairs are skipped and pairs for which the metric returns `None` are dropped.
+///
+/// # Arguments
+///
+/// * `elements` - The input set vector containing elements to be clustered.
+/// * `min_similarity` - The minimum score to consider two elements similar.
+/// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+/// * `similarity_metric` - The similarity metric to apply, `None` meaning "below threshold".
+///
+/// # Return
+///
+/// The qualifying similarity triplets, in no particular order, and the number of pairs compared.
+fn score_pairs<T, I, M>(
+    elements: &[T],
+    min_similarity: Similarity,
+    index_pair_iterator: &mut I,
+    similarity_metric: M,
+) -> (Vec<(Index, Index, Similarity)>, usize)
+    where
+        T: Sync + Send,
+        I: IndexPairSource,
+        M: Fn(&T, &T) -> Option<Similarity> + Sync,
+{
+    let mut compared_pairs = 0;
+    let similarity_triplets =
+        qualifying_pairs(
+            elements,
+            min_similarity,
+            index_pair_iterator,
+            &similarity_metric,
+            &mut compared_pairs,
+        )
+            .collect::<Vec<(Index, Index, Similarity)>>();
+
+    (similarity_triplets, compared_pairs)
+}
+
+/// Measure the similarity of each index pair in parallel, yielding the pairs meeting a minimum
+/// similarity. Self pairs are skipped and pairs for which the metric returns `None` are dropped.
+///
+/// # Arguments
+///
+/// * `compared_pairs` - The counter of pairs compared, incremented as pairs are drawn.
+///
+/// # Return
+///
+/// The parallel iterator over qualifying similarity triplets.
+fn qualifying_pairs<'a, T, I, M>(
+    elements: &'a [T],
+    min_similarity: Similarity,
+    index_pair_iterator: &'a mut I,
+    similarity_metric: &'a M,
+    compared_pairs: &'a mut usize,
+) -> impl ParallelIterator<Item=(Index, Index, Similarity)> + 'a
+    where
+        T: Sync + Send,
+        I: IndexPairSource,
+        M: Fn(&T, &T) -> Option<Similarity> + Sync,
+{
+    index_pair_iterator
+        .filter(|(row, column)| row != column)
+        .inspect(move |_| *compared_pairs += 1)
+        .par_bridge()
+        .filter_map(move |(row, column)|
+            similarity_metric(&elements[row], &elements[column])
+                .map(|similarity| {
+                    assert!(!similarity.is_nan(), "Metric returned NaN for pair ({}, {})", row, column);
+                    (row, column, similarity)
+                }))
+        .filter(move |(_, _, similarity)| *similarity > 0.0 && *similarity >= min_similarity)
+}
+
+/// Implementation of `std::ops::Index` for similarity matrix.
+impl BracketedIndex<Index> for SimilarityMatrix {
+    /// The data type of values returned by the indexing operator (`[]`).
+    type Output = Row;
+
+    /// Return the row at a given position position.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the desired row in this matrix.
+    ///
+    /// # Return
+    ///
+    /// The row at `index` position.
+    fn index(&self, index: Size) -> &Self::Output {
+        &self.rows[index]
+    }
+}
+
+/// Creation of a matrix from adjacency lists, where each row holds `(sibling, similarity)` pairs
+/// for the element at its position. Pairs need only be given in one direction but, if given in
+/// both, their similarities must agree. Self-similarities and non-positive similarities are
+/// ignored, while NaN similarities are rejected.
+impl TryFrom<Vec<Vec<(Index, Similarity)>>> for SimilarityMatrix {
+    type Error = MatrixError;
+
+    fn try_from(adjacency_lists: Vec<Vec<(Index, Similarity)>>) -> Result<SimilarityMatrix, MatrixError> {
+        let size = adjacency_lists.len();
+        if size == 0 {
+            return Err(MatrixError::Empty);
+        }
+
+        let mut pair_similarities: HashMap<(Index, Index), Similarity> = HashMap::new();
+        for (row, siblings) in adjacency_lists.into_iter().enumerate() {
+            for (sibling, similarity) in siblings {
+                if sibling >= size {
+                    return Err(MatrixError::SiblingOutOfRange { row, sibling, size });
+                }
+                if similarity.is_nan() {
+                    return Err(MatrixError::NotANumber { row, column: sibling });
+                }
+                if sibling == row || similarity <= 0.0 {
+                    continue;
+                }
+
+                let pair = (row.min(sibling), row.max(sibling));
+                match pair_similarities.insert(pair, similarity) {
+                    Some(previous) if previous != similarity =>
+                        return Err(MatrixError::Asymmetric { row, column: sibling }),
+                    _ => {}
+                }
+            }
+        }
+
+        let similarity_triplets =
+            pair_similarities.into_iter()
+                .map(|((row, column), similarity)| (row, column, similarity))
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        let mut similarity_matrix =
+            SimilarityMatrix::from_qualifying_triplets(size, 0.0, similarity_triplets);
+        similarity_matrix.sort_rows();
+        Ok(similarity_matrix)
+    }
+}
+
+/// Implementation of `std::ops::Index` for `Score`.
+///
+/// Since the matrix is sparse, `row[sibling]` only reflects stored scores: it returns `0.0` for
+/// any sibling below the matrix's `min_similarity`, which doesn't mean the two elements aren't
+/// similar at all. Use `SimilarityMatrix::recompute_similarity` to obtain the actual value.
+impl BracketedIndex<Index> for Row {
+    /// The data type of values returned by the indexing operator (`[]`).
+    type Output = Similarity;
+
+    /// Return the stored similarity of a sibling.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the sibling.
+    ///
+    /// # Return
+    ///
+    /// The sibling's stored similarity or `0.0` if none is stored.
+    fn index(&self, index: Size) -> &Self::Output {
+        self.scores
+            .iter()
+            .find(|score| score.sibling_index == index)
+            .map(|score| &score.similarity)
+            .unwrap_or(&0.0)
+    }
+}
+
+impl Row {
+    pub fn new(scores: Vec<Score>) -> Row {
+        Row { scores }
+    }
+
+    /// Return this row's weight, the measure of the row element's strength used to rank seeds.
+    ///
+    /// # Return
+    ///
+    /// The row's sibling count and the sum of its siblings' similarities.
+    pub fn weight(&self) -> (Size, Similarity) {
+        let similarity_sum =
+            self.scores.iter()
+                .map(|score| score.similarity)
+                .sum::<Similarity>();
+        (self.scores.len(), similarity_sum)
+    }
+
+    /// Ascertain whether this row's scores are sorted by descending similarity.
+    fn is_sorted(&self) -> bool {
+        self.scores.windows(2).all(|pair| pair[0].similarity >= pair[1].similarity)
+    }
+
+    /// Convert this row's scores into compact scores.
+    ///
+    /// # Return
+    ///
+    /// The compact scores or the error raised by the first sibling index exceeding `u32::MAX`.
+    pub fn to_compact(&self) -> Result<Vec<CompactScore>, TryFromIntError> {
+        self.scores.iter()
+            .map(CompactScore::try_from)
+            .collect::<Result<Vec<CompactScore>, TryFromIntError>>()
+    }
+
+    /// Create a row from compact scores.
+    ///
+    /// # Arguments
+    ///
+    /// * `scores` - The compact scores to widen.
+    ///
+    /// # Return
+    ///
+    /// The new row.
+    pub fn from_compact(scores: &[CompactScore]) -> Row {
+        Row::new(scores.iter().map(Score::from).collect::<Vec<Score>>())
+    }
+
+    /// Return the siblings at least as similar as a threshold, a building block for manually
+    /// constructing clusters.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity` - The minimum similarity, inclusive. Values outside `[0.0, 1.0]` are clamped
+    ///   to that range; NaN is rejected.
+    ///
+    /// # Return
+    ///
+    /// The qualifying sibling indices by descending similarity, equally similar siblings by
+    /// ascending index, regardless of whether the row's scores are stored sorted.
     pub fn cut_at(&self, similarity: Similarity) -> Vec<Index> {
+        let mut scores = self.scores_at_or_above(similarity);
+        scores.sort_by(|score_1, score_2|
+            score_2.similarity.partial_cmp(&score_1.similarity).unwrap()
+                .then(score_1.sibling_index.cmp(&score_2.sibling_index)));
+
+        scores.iter()
+            .map(|score| score.sibling_index)
+            .collect::<Vec<Index>>()
+    }
+
+    /// Return the siblings at least as similar as a threshold in ascending index order, handy
+    /// for building clusters directly comparable to the clusterer's output.
+    ///
+    /// # Arguments
+    ///
+    /// * `similarity` - The minimum similarity, inclusive. Values outside `[0.0, 1.0]` are clamped
+    ///   to that range; NaN is rejected.
+    ///
+    /// # Return
+    ///
+    /// The qualifying sibling indices in ascending order.
+    pub fn cut_at_sorted(&self, similarity: Similarity) -> Vec<Index> {
+        let mut siblings =
+            self.scores_at_or_above(similarity).iter()
+                .map(|score| score.sibling_index)
+                .collect::<Vec<Index>>();
+        siblings.sort_unstable();
+        siblings
+    }
+
+    /// Return the scores at least as similar as a threshold clamped to `[0.0, 1.0]`.
+    fn scores_at_or_above(&self, similarity: Similarity) -> Vec<&Score> {
+        assert!(!similarity.is_nan(), "Cut similarity must not be NaN");
+        let similarity = similarity.clamp(0.0, 1.0);
+
         self.scores.iter()
             .filter(|score| score.similarity >= similarity)
+            .collect::<Vec<&Score>>()
+    }
+
+    /// Rank this row's siblings by descending similarity. Equally similar siblings are ranked by
+    /// ascending index so that the ranking doesn't depend on the (possibly parallel) order in
+    /// which scores were stored.
+    ///
+    /// # Arguments
+    ///
+    /// * `excluding` - The siblings to leave out.
+    ///
+    /// # Return
+    ///
+    /// The ranked sibling indices.
+    pub fn ranked_siblings(&self, excluding: &HashSet<Index>) -> Vec<Index> {
+        self.ranked_siblings_by(excluding, |score_1, score_2|
+            score_2.similarity.partial_cmp(&score_1.similarity).unwrap()
+                .then(score_1.sibling_index.cmp(&score_2.sibling_index)))
+    }
+
+    /// Rank this row's siblings using a custom comparator, for instance one favoring
+    /// well-connected siblings over merely similar ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `excluding` - The siblings to leave out.
+    /// * `cmp` - The comparator ordering scores from best to worst.
+    ///
+    /// # Return
+    ///
+    /// The ranked sibling indices.
+    pub fn ranked_siblings_by<F>(&self, excluding: &HashSet<Index>, cmp: F) -> Vec<Index>
+        where F: Fn(&Score, &Score) -> Ordering
+    {
+        let mut siblings =
+            self.scores.iter()
+                .filter(|score| !excluding.contains(&score.sibling_index))
+                .collect::<Vec<&Score>>();
+
+        siblings.sort_by(|score_1, score_2| cmp(score_1, score_2));
+
+        siblings.iter()
             .map(|score| score.sibling_index)
             .collect::<Vec<Index>>()
     }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use strsim::normalized_damerau_levenshtein;
+
+    use crate::index_pair::cartesian::CartesianIndexPairIterator;
+    use crate::index_pair::ngrams::NGramPairs;
+    use crate::utils::{string_vec, unique_temp_path};
+
+    use super::*;
+
+    type Scores = (Index, Index, Similarity);
+
+    #[test]
+    #[should_panic]
+    fn new_sparse_similarity_matrix_rejects_zero_size() {
+        let empty_vec = vec![];
+
+        SimilarityMatrix::new(
+            &empty_vec,
+            0.6,
+            &mut CartesianIndexPairIterator::new(2),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+    }
+
+    #[test]
+    fn matrix_holds_correct_scores() {
+        let (names, scores) = name_scores();
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            0.0,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        check_scores(&similarity_matrix, names.len(), scores);
+    }
+
+    #[test]
+    fn unsorted_matrix_holds_correct_scores() {
+        let (names, scores) = name_scores();
+
+        let mut similarity_matrix = SimilarityMatrix::new_unsorted(
+            &names,
+            0.0,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+        assert!(!similarity_matrix.has_sorted_rows());
+
+        check_scores(&similarity_matrix, names.len(), scores);
+
+        similarity_matrix.sort_rows();
+        assert!(similarity_matrix.has_sorted_rows());
+        for row in &similarity_matrix.rows {
+            assert!(row.scores.windows(2).all(|pair| pair[0].similarity >= pair[1].similarity));
+        }
+    }
+
+    #[test]
+    fn gathers_build_stats() {
+        let (names, _) = name_scores();
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            0.5,
+            &mut NGramPairs::new(&names, 2),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        let build_stats = similarity_matrix.build_stats().unwrap();
+        assert_eq!(build_stats.size, 8);
+        assert_eq!(build_stats.cartesian_pairs(), 28);
+        assert_eq!(build_stats.compared_pairs, 21);
+        assert_eq!(build_stats.qualifying_pairs, 5);
+        assert_eq!(build_stats.reduction_ratio(), 0.25);
+        assert_eq!(build_stats.nonzero_rows, 7);
+
+        assert!(similarity_matrix.spin_off(&[0, 1], 0.5).build_stats().is_none());
+    }
+
+    #[test]
+    fn matrix_from_short_circuiting_metric() {
+        let (names, scores) = name_scores();
+        let min_similarity = 0.5;
+
+        let similarity_matrix = SimilarityMatrix::new_filtered(
+            &names,
+            min_similarity,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1: &String, t2: &String| {
+                let (len_1, len_2) = (t1.chars().count(), t2.chars().count());
+                if len_1.min(len_2) < (min_similarity * len_1.max(len_2) as f64) as usize {
+                    None
+                } else {
+                    Some(normalized_damerau_levenshtein(t1, t2))
+                }
+            },
+        );
+
+        let scores =
+            scores.into_iter()
+                .filter(|(_, _, similarity)| *similarity >= min_similarity)
+                .collect::<Vec<Scores>>();
+        check_scores(&similarity_matrix, names.len(), scores);
+        assert_eq!(similarity_matrix.build_stats().unwrap().qualifying_pairs, 5);
+    }
+
+    #[test]
+    fn symmetrizes_asymmetric_metric() {
+        // A name's similarity to a longer name it abbreviates; nothing is similar to its abbreviation.
+        let names = string_vec(vec!["jo", "john", "jonathan"]);
+        let abbreviates = |t1: &String, t2: &String| {
+            if t1.len() < t2.len() && t2.starts_with(t1.as_str()) {
+                t1.len() as Similarity / t2.len() as Similarity
+            } else {
+                0.0
+            }
+        };
+
+        let matrix_with = |symmetrization| SimilarityMatrix::new_asymmetric(
+            &names,
+            0.0,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            abbreviates,
+            symmetrization,
+        );
+
+        check_scores(&matrix_with(Symmetrization::Max), 3, vec![(0, 1, 0.5), (0, 2, 0.25), (1, 2, 0.0)]);
+        check_scores(&matrix_with(Symmetrization::Mean), 3, vec![(0, 1, 0.25), (0, 2, 0.125), (1, 2, 0.0)]);
+        assert!(matrix_with(Symmetrization::Min).is_empty());
+    }
+
+    #[test]
+    fn detects_matrix_without_edges() {
+        let names = string_vec(vec!["alejandro", "marlene", "ricardo"]);
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+
+        let result = SimilarityMatrix::try_new(&names, 0.9, &mut CartesianIndexPairIterator::new(3), metric);
+        match result {
+            Err(MatrixError::NoEdges { min_similarity }) => assert_eq!(min_similarity, 0.9),
+            _ => panic!("Expected NoEdges error"),
+        }
+
+        let similarity_matrix = SimilarityMatrix::new(&names, 0.9, &mut CartesianIndexPairIterator::new(3), metric);
+        assert!(similarity_matrix.is_empty());
+        assert_eq!(similarity_matrix.build_stats().unwrap().nonzero_rows, 0);
+
+        let result = SimilarityMatrix::try_new(&names, 0.1, &mut CartesianIndexPairIterator::new(3), metric);
+        assert!(!result.unwrap().is_empty());
+    }
+
+    #[test]
+    fn matrix_from_distance_holds_correct_scores() {
+        let points: Vec<f64> = vec![0.0, 1.0, 2.5, 10.0];
+
+        let similarity_matrix = SimilarityMatrix::new_from_distance(
+            &points,
+            4.0,
+            &mut CartesianIndexPairIterator::new(points.len()),
+            |p1: &f64, p2: &f64| (p1 - p2).abs(),
+        );
+
+        check_scores(&similarity_matrix, 4, vec![
+            (0, 1, 0.75),
+            (0, 2, 0.375),
+            (1, 2, 0.625),
+            (0, 3, 0.0),
+            (2, 3, 0.0),
+        ]);
+        assert_eq!(similarity_matrix.similarity_values, vec![0.375, 0.625, 0.75]);
+    }
 
-    pub fn ranked_siblings(&self, excluding: &HashSet<Index>) -> Vec<Index> {
-        let mut siblings =
-            self.scores.iter()
-                .filter(|score| !excluding.contains(&score.sibling_index))
-                .collect::<Vec<&Score>>();
+    #[test]
+    fn matrix_from_distance_keeps_pairs_at_max_distance() {
+        let points: Vec<f64> = vec![0.0, 2.0, 4.0];
 
-        siblings.sort_by(|score_1, score_2|
-            (*score_2).similarity.partial_cmp(&score_1.similarity).unwrap());
+        let similarity_matrix = SimilarityMatrix::new_from_distance(
+            &points,
+            2.0,
+            &mut CartesianIndexPairIterator::new(points.len()),
+            |p1: &f64, p2: &f64| (p1 - p2).abs(),
+        );
 
-        siblings.iter()
-            .map(|score| score.sibling_index)
-            .collect::<Vec<Index>>()
+        assert_eq!(similarity_matrix[0][1], Similarity::MIN_POSITIVE);
+        assert_eq!(similarity_matrix[1][2], Similarity::MIN_POSITIVE);
+        assert!(similarity_matrix[0].scores.iter().all(|score| score.sibling_index != 2));
+        assert_eq!(similarity_matrix[1].scores.len(), 2);
     }
-}
 
+    #[test]
+    fn ignores_self_similarity() {
+        let similarity_matrix = SimilarityMatrix::from_triplets(
+            5,
+            0.5,
+            vec![(3, 3, 1.0), (0, 1, 0.9), (1, 2, 0.8), (2, 4, 0.4)],
+        );
 
-#[cfg(test)]
-mod tests {
-    use strsim::normalized_damerau_levenshtein;
+        check_scores(&similarity_matrix, 5, vec![(0, 1, 0.9), (1, 2, 0.8), (2, 4, 0.0), (3, 3, 0.0)]);
+        assert!(similarity_matrix[3].scores.is_empty());
+        assert_eq!(similarity_matrix.rank_by_weight(RankMode::ByWeightedDegree)[..3], [1, 0, 2]);
+        assert_eq!(similarity_matrix.similarity_values, vec![0.8, 0.9]);
+    }
 
-    use crate::index_pair::cartesian::CartesianIndexPairIterator;
-    use crate::utils::string_vec;
+    #[test]
+    fn stores_repeated_triplets_once() {
+        let similarity_matrix = SimilarityMatrix::from_triplets(
+            3,
+            0.0,
+            vec![(0, 1, 0.9), (1, 0, 0.9), (1, 2, 0.5), (1, 2, 0.6), (2, 1, 0.4)],
+        );
 
-    use super::*;
+        check_scores(&similarity_matrix, 3, vec![(0, 1, 0.9), (1, 2, 0.6)]);
+        assert_eq!(similarity_matrix[1].weight(), (2, 1.5));
+        assert_eq!(similarity_matrix.similarity_values, vec![0.6, 0.9]);
+    }
 
-    type Scores = (Index, Index, Similarity);
+    #[test]
+    #[should_panic]
+    fn from_triplets_rejects_nan() {
+        SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.9), (1, 2, Similarity::NAN)]);
+    }
 
     #[test]
     #[should_panic]
-    fn new_sparse_similarity_matrix_rejects_zero_size() {
-        let empty_vec = vec![];
+    fn new_rejects_nan_similarity() {
+        let elements = vec![0.5, 0.7, 0.9];
 
         SimilarityMatrix::new(
-            &empty_vec,
-            0.6,
-            &mut CartesianIndexPairIterator::new(2),
-            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+            &elements,
+            0.0,
+            &mut CartesianIndexPairIterator::new(elements.len()),
+            |s1: &f64, s2: &f64| if *s1 == 0.9 || *s2 == 0.9 { Similarity::NAN } else { 0.5 },
         );
     }
 
     #[test]
-    fn matrix_holds_correct_scores() {
-        let (names, scores) = name_scores();
+    #[should_panic]
+    fn from_triplets_rejects_out_of_range_index() {
+        SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.9), (1, 3, 0.8)]);
+    }
 
-        let similarity_matrix = SimilarityMatrix::new(
-            &names,
+    #[test]
+    fn sizes_rows_to_their_degree() {
+        let similarity_matrix = SimilarityMatrix::from_triplets(
+            4,
             0.0,
-            &mut CartesianIndexPairIterator::new(names.len()),
-            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+            vec![(0, 1, 0.9), (0, 2, 0.8), (0, 3, 0.7), (1, 2, 0.6), (2, 2, 1.0)],
         );
 
-        check_scores(&similarity_matrix, names.len(), scores);
+        for row in &similarity_matrix.rows {
+            assert_eq!(row.scores.capacity(), row.scores.len());
+        }
+        assert_eq!(similarity_matrix[0].scores.len(), 3);
+    }
+
+    #[test]
+    fn reads_neighbor_file() {
+        let path = unique_temp_path("neighbors.txt");
+        std::fs::write(&path, "0: 1:0.9 2:0.4\n1: 0:0.8 3:0.7\n\n3: 3:1.0 2:0.6\n").unwrap();
+
+        let similarity_matrix = SimilarityMatrix::from_neighbor_file(&path, 5, 0.5).unwrap();
+
+        check_scores(&similarity_matrix, 5, vec![
+            (0, 1, 0.9),
+            (0, 2, 0.0),
+            (1, 3, 0.7),
+            (2, 3, 0.6),
+            (3, 3, 0.0),
+        ]);
+        assert!(similarity_matrix[4].scores.is_empty());
+        assert_eq!(similarity_matrix.similarity_values, vec![0.6, 0.7, 0.9]);
+    }
+
+    #[test]
+    fn rejects_malformed_neighbor_file() {
+        let path = unique_temp_path("bad-neighbors.txt");
+
+        std::fs::write(&path, "0: 1:0.9\n1 0:0.9\n").unwrap();
+        match SimilarityMatrix::from_neighbor_file(&path, 2, 0.5) {
+            Err(MatrixError::Parse { line, .. }) => assert_eq!(line, 2),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        std::fs::write(&path, "0: 2:0.9\n").unwrap();
+        match SimilarityMatrix::from_neighbor_file(&path, 2, 0.5) {
+            Err(MatrixError::IndexOutOfRange { index, .. }) => assert_eq!(index, 2),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        std::fs::write(&path, "0: 1:NaN\n").unwrap();
+        assert!(matches!(
+            SimilarityMatrix::from_neighbor_file(&path, 2, 0.5),
+            Err(MatrixError::NotANumber { row: 0, column: 1 }),
+        ));
     }
 
     #[test]
@@ -324,11 +2097,8 @@ mod tests {
             4, // 2: marlene
             5, // 3: marleny
             6, // 4: malrene
-        ]
-            .iter()
-            .map(|index| *index)
-            .collect::<Vec<Index>>();
-        let size = (&partial_indices).len();
+        ];
+        let size = partial_indices.len();
 
         let partial_scores: Vec<(Index, Index, Similarity)> = vec![
             (0, 1, 0.8333333333333334), // (martha, marta)
@@ -356,6 +2126,492 @@ mod tests {
         check_scores(&similarity_matrix, size, partial_scores)
     }
 
+    #[test]
+    fn collects_distinct_similarity_values_exactly() {
+        let elements = vec![0.3, 0.1 + 0.2, 0.3, 0.9, 0.5];
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &elements,
+            0.0,
+            &mut CartesianIndexPairIterator::new(elements.len()),
+            |s1: &f64, s2: &f64| s1.min(*s2),
+        );
+
+        assert_eq!(similarity_matrix.similarity_values, vec![0.3, 0.1 + 0.2, 0.5]);
+    }
+
+    /// Compare bit-pattern keying against the former string round-tripping on 100k edges. Run
+    /// with `cargo test --release -- --ignored --nocapture` to see the timings.
+    #[test]
+    #[ignore]
+    fn benchmarks_distinct_similarity_values() {
+        use std::time::Instant;
+
+        let size = 100_000;
+        let rows = (0..size)
+            .map(|index| {
+                let similarity = |offset: Index| ((index * 7919 + offset) % 10_007) as Similarity / 10_007.0;
+                Row::new(vec![
+                    Score { sibling_index: (index + 1) % size, similarity: similarity(0) },
+                    Score { sibling_index: (index + size - 1) % size, similarity: similarity(1) },
+                ])
+            })
+            .collect::<Vec<Row>>();
+
+        let start = Instant::now();
+        let by_string = itertools::sorted(
+            rows.iter()
+                .flat_map(|row| row.scores.iter().map(|score| score.similarity.to_string()))
+                .collect::<HashSet<String>>()
+        )
+            .map(|similarity| similarity.parse::<Similarity>().unwrap())
+            .collect::<Vec<Similarity>>();
+        let string_elapsed = start.elapsed();
+
+        let start = Instant::now();
+        let by_bits = distinct_similarities(&rows);
+        let bits_elapsed = start.elapsed();
+
+        println!("strings: {:?}, bit patterns: {:?}", string_elapsed, bits_elapsed);
+        assert_eq!(by_bits.len(), 10_007);
+        assert!(by_bits.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(by_bits, by_string);
+    }
+
+    #[test]
+    fn selects_threshold_candidates() {
+        let (names, _) = name_scores();
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            0.0,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+        let values = &similarity_matrix.similarity_values;
+        assert_eq!(values.len(), 11);
+
+        assert!(similarity_matrix.threshold_candidates(0).is_empty());
+        assert_eq!(similarity_matrix.threshold_candidates(1), vec![values[5]]);
+        assert_eq!(similarity_matrix.threshold_candidates(3), vec![values[0], values[5], values[10]]);
+        assert_eq!(similarity_matrix.threshold_candidates(20), *values);
+    }
+
+    #[test]
+    fn rebuilds_similarity_values() {
+        let mut similarity_matrix = SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.5), (1, 2, 0.75)]);
+
+        similarity_matrix.rows[1].scores.retain(|score| score.sibling_index != 2);
+        similarity_matrix.rows[2].scores.clear();
+        similarity_matrix.rows[0].scores.push(Score { sibling_index: 2, similarity: 0.25 });
+        similarity_matrix.rows[2].scores.push(Score { sibling_index: 0, similarity: 0.25 });
+        assert_eq!(similarity_matrix.similarity_values, vec![0.5, 0.75]);
+
+        similarity_matrix.rebuild_similarity_values();
+        assert_eq!(similarity_matrix.similarity_values, vec![0.25, 0.5]);
+    }
+
+    #[test]
+    fn builds_similarity_histogram() {
+        let triplets = vec![(0, 1, 0.5), (1, 2, 0.55), (2, 3, 0.75), (3, 4, 0.95), (0, 4, 1.0)];
+        let similarity_matrix = SimilarityMatrix::from_triplets(5, 0.5, triplets);
+
+        assert_eq!(
+            similarity_matrix.similarity_histogram(2),
+            vec![(0.5, 2), (0.75, 3)],
+        );
+        assert_eq!(
+            similarity_matrix.similarity_histogram(5),
+            vec![(0.5, 2), (0.6, 0), (0.7, 1), (0.8, 0), (0.9, 2)],
+        );
+        assert_eq!(similarity_matrix.similarity_histogram(1), vec![(0.5, 5)]);
+    }
+
+    #[test]
+    fn computes_similarity_percentiles() {
+        let triplets = vec![(0, 1, 0.5), (1, 2, 0.6), (2, 3, 0.6), (3, 4, 0.9), (0, 4, 1.0)];
+        let similarity_matrix = SimilarityMatrix::from_triplets(5, 0.5, triplets);
+
+        assert_eq!(similarity_matrix.similarity_percentile(0.0), 0.5);
+        assert_eq!(similarity_matrix.similarity_percentile(50.0), 0.6);
+        assert!((similarity_matrix.similarity_percentile(62.5) - 0.75).abs() < 1e-9);
+        assert_eq!(similarity_matrix.similarity_percentile(100.0), 1.0);
+
+        assert_eq!(SimilarityMatrix::from_triplets(3, 0.4, vec![]).similarity_percentile(90.0), 0.4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn similarity_percentile_rejects_out_of_range() {
+        SimilarityMatrix::from_triplets(2, 0.0, vec![(0, 1, 0.5)]).similarity_percentile(101.0);
+    }
+
+    #[test]
+    fn verifies_elements_fingerprint() {
+        let names = string_vec(vec!["alejandro", "alexandro", "marlene"]);
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+
+        let similarity_matrix =
+            SimilarityMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric);
+        assert_eq!(similarity_matrix.elements_fingerprint(), None);
+        assert!(!similarity_matrix.verify_against(&names));
+
+        let similarity_matrix = similarity_matrix.with_elements_fingerprint(&names);
+        assert!(similarity_matrix.verify_against(&names));
+
+        let similarity_matrix =
+            SimilarityMatrix::new_fingerprinted(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric);
+        assert!(similarity_matrix.verify_against(&names));
+        assert!(!similarity_matrix.verify_against(&string_vec(vec!["alejandro", "alexandro", "marleny"])));
+        assert!(!similarity_matrix.verify_against(&string_vec(vec!["alexandro", "alejandro", "marlene"])));
+
+        let mut loaded = SimilarityMatrix::from_triplets(3, 0.5, vec![]);
+        loaded.set_elements_fingerprint(similarity_matrix.elements_fingerprint().unwrap());
+        assert!(loaded.verify_against(&names));
+    }
+
+    #[test]
+    fn queries_corpus_in_batch() {
+        let corpus = string_vec(vec!["alejandro", "alexandro", "marlene", "marleny", "ricardo"]);
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+        let similarity_matrix =
+            SimilarityMatrix::new(&corpus, 0.5, &mut CartesianIndexPairIterator::new(corpus.len()), metric);
+
+        let queries = string_vec(vec!["alejandra", "marlen", "zzz"]);
+        let neighbors = similarity_matrix.batch_query(&queries, &corpus, metric, 1);
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(neighbors[0], vec![(0, metric(&queries[0], &corpus[0]))]);
+        assert_eq!(neighbors[1].iter().map(|(index, _)| *index).collect::<Vec<Index>>(), vec![2]);
+        assert!(neighbors[2].is_empty());
+
+        let all_neighbors = similarity_matrix.batch_query(&queries, &corpus, metric, 10);
+        assert_eq!(all_neighbors[1].iter().map(|(index, _)| *index).collect::<Vec<Index>>(), vec![2, 3]);
+
+        let blocked = similarity_matrix.batch_query_blocked(&queries, &corpus, metric, 10, |_| vec![3, 1, 3]);
+        assert_eq!(blocked[1].iter().map(|(index, _)| *index).collect::<Vec<Index>>(), vec![3]);
+    }
+
+    #[test]
+    fn sorts_edges_globally() {
+        let triplets = vec![(3, 4, 0.5), (0, 1, 0.9), (2, 1, 0.5), (0, 4, 0.7)];
+        let similarity_matrix = SimilarityMatrix::from_triplets(5, 0.0, triplets);
+
+        assert_eq!(
+            similarity_matrix.sorted_edges(true),
+            vec![(0, 1, 0.9), (0, 4, 0.7), (1, 2, 0.5), (3, 4, 0.5)],
+        );
+        assert_eq!(
+            similarity_matrix.sorted_edges(false),
+            vec![(1, 2, 0.5), (3, 4, 0.5), (0, 4, 0.7), (0, 1, 0.9)],
+        );
+    }
+
+    #[test]
+    fn snaps_to_nearest_value() {
+        let (names, _) = name_scores();
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            0.4,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        assert_eq!(similarity_matrix.nearest_value(0.0), 0.4285714285714286);
+        assert_eq!(similarity_matrix.nearest_value(0.56), 0.5555555555555556);
+        assert_eq!(similarity_matrix.nearest_value(0.84), 0.8333333333333334);
+        assert_eq!(similarity_matrix.nearest_value(1.0), 0.8571428571428572);
+    }
+
+    #[test]
+    fn matrix_creates_spin_off_from_mask() {
+        let (names, _) = name_scores();
+
+        let similarity_matrix = SimilarityMatrix::new(
+            &names,
+            0.0,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |t1: &String, t2: &String| normalized_damerau_levenshtein(t1.as_str(), t2.as_str()),
+        );
+
+        let keep = vec![false, false, true, true, true, true, true, false];
+        let masked_matrix = similarity_matrix.spin_off_mask(&keep, 0.4);
+        let indexed_matrix = similarity_matrix.spin_off(&[2, 3, 4, 5, 6], 0.4);
+
+        assert_eq!(masked_matrix.size(), indexed_matrix.size());
+        for row in 0..masked_matrix.size() {
+            for column in 0..masked_matrix.size() {
+                assert_eq!(masked_matrix[row][column], indexed_matrix[row][column]);
+            }
+        }
+    }
+
+    #[test]
+    fn matrix_creates_filtered_spin_off() {
+        let triplets = vec![(0, 1, 0.9), (0, 2, 0.8), (1, 2, 0.7), (2, 3, 0.3), (3, 4, 0.9)];
+        let similarity_matrix = SimilarityMatrix::from_triplets(5, 0.0, triplets);
+
+        let (spin_off, retained) = similarity_matrix.spin_off_filtered(&[0, 1, 2, 3], 0.5, Some(1.0));
+        assert_eq!(retained, vec![0, 1, 2]);
+        assert_eq!(spin_off.size(), 3);
+        assert_eq!(spin_off[1][2], 0.7);
+
+        let (spin_off, retained) = similarity_matrix.spin_off_filtered(&[0, 1, 2, 3], 0.5, None);
+        assert_eq!(retained, vec![0, 1, 2, 3]);
+        assert!(spin_off[3].scores.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn spin_off_mask_rejects_wrong_length() {
+        SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.9)]).spin_off_mask(&[true, false], 0.0);
+    }
+
+    #[test]
+    fn lists_top_neighbors() {
+        let triplets = vec![(0, 1, 0.5), (0, 2, 0.9), (0, 3, 0.7), (1, 2, 0.6)];
+        let similarity_matrix = SimilarityMatrix::from_triplets(5, 0.0, triplets);
+
+        assert_eq!(similarity_matrix.top_neighbors(0, 2), vec![(2, 0.9), (3, 0.7)]);
+        assert_eq!(similarity_matrix.top_neighbors(1, 10), vec![(2, 0.6), (0, 0.5)]);
+        assert_eq!(similarity_matrix.top_neighbors(0, 0), vec![]);
+        assert_eq!(similarity_matrix.top_neighbors(4, 3), vec![]);
+    }
+
+    #[test]
+    fn lists_top_neighbors_after_editing_rows() {
+        let triplets = vec![(0, 1, 0.5), (0, 2, 0.9)];
+        let mut similarity_matrix = SimilarityMatrix::from_triplets(4, 0.0, triplets);
+        assert!(similarity_matrix.has_sorted_rows());
+
+        similarity_matrix.rows[0].scores.push(Score { sibling_index: 3, similarity: 0.7 });
+        similarity_matrix.rows[3].scores.push(Score { sibling_index: 0, similarity: 0.7 });
+        assert!(!similarity_matrix.has_sorted_rows());
+        assert_eq!(similarity_matrix.top_neighbors(0, 2), vec![(2, 0.9), (3, 0.7)]);
+
+        similarity_matrix.sort_rows();
+        assert!(similarity_matrix.has_sorted_rows());
+    }
+
+    #[test]
+    #[should_panic]
+    fn top_neighbors_rejects_out_of_range_index() {
+        SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.9)]).top_neighbors(3, 1);
+    }
+
+    #[test]
+    fn recomputes_unstored_similarity() {
+        let names = string_vec(vec!["alejandro", "alejo", "marlene"]);
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+        let similarity_matrix =
+            SimilarityMatrix::new(&names, 0.6, &mut CartesianIndexPairIterator::new(names.len()), metric);
+
+        assert_eq!(similarity_matrix[0][1], 0.0);
+        assert_eq!(similarity_matrix.recompute_similarity(0, 1, &names, metric), 5.0 / 9.0);
+    }
+
+    #[test]
+    fn converts_adjacency_lists() {
+        let similarity_matrix = SimilarityMatrix::try_from(vec![
+            vec![(1, 0.5), (2, 0.9)],
+            vec![(0, 0.5)],
+            vec![(2, 1.0)],
+        ]).unwrap();
+
+        check_scores(&similarity_matrix, 3, vec![(0, 2, 0.9), (0, 1, 0.5)]);
+        assert!(similarity_matrix.has_sorted_rows());
+        assert_eq!(similarity_matrix.similarity_values, vec![0.5, 0.9]);
+
+        match SimilarityMatrix::try_from(vec![vec![(1, 0.5)], vec![(0, 0.6)]]) {
+            Err(MatrixError::Asymmetric { row: 1, column: 0 }) => {}
+            _ => panic!("Expected Asymmetric error"),
+        }
+        match SimilarityMatrix::try_from(vec![vec![(2, 0.5)], vec![]]) {
+            Err(MatrixError::SiblingOutOfRange { row: 0, sibling: 2, size: 2 }) => {}
+            _ => panic!("Expected SiblingOutOfRange error"),
+        }
+        assert!(matches!(SimilarityMatrix::try_from(vec![]), Err(MatrixError::Empty)));
+        assert!(matches!(
+            SimilarityMatrix::try_from(vec![vec![(1, 0.5)], vec![(2, Similarity::NAN)], vec![]]),
+            Err(MatrixError::NotANumber { row: 1, column: 2 }),
+        ));
+    }
+
+    #[test]
+    fn concatenates_matrices() {
+        let a = SimilarityMatrix::from_triplets(2, 0.5, vec![(0, 1, 0.6)]);
+        let b = SimilarityMatrix::from_triplets(3, 0.7, vec![(0, 1, 0.8), (1, 2, 0.9)]);
+
+        let similarity_matrix = SimilarityMatrix::concat(a, b, vec![(1, 0, 0.75), (0, 2, 0.5)]);
+
+        check_scores(&similarity_matrix, 5, vec![(1, 2, 0.75), (2, 3, 0.8), (3, 4, 0.9)]);
+        assert_eq!(similarity_matrix[0][1], 0.0);
+        assert_eq!(similarity_matrix[0][4], 0.0);
+        assert_eq!(similarity_matrix.min_similarity(), 0.7);
+        assert_eq!(similarity_matrix.similarity_values, vec![0.75, 0.8, 0.9]);
+        assert!(similarity_matrix.has_sorted_rows());
+    }
+
+    #[test]
+    fn writes_graphml() {
+        let similarity_matrix = SimilarityMatrix::from_triplets(3, 0.0, vec![(0, 1, 0.5), (2, 1, 0.75)]);
+        let labels = string_vec(vec!["a&b", "<c>", "d"]);
+
+        let mut out = Vec::new();
+        similarity_matrix.write_graphml(&mut out, Some(&labels)).unwrap();
+        let graphml = String::from_utf8(out).unwrap();
+
+        assert!(graphml.contains(r#"<node id="n0">"#));
+        assert!(graphml.contains(r#"<data key="label">a&amp;b</data>"#));
+        assert!(graphml.contains(r#"<data key="label">&lt;c&gt;</data>"#));
+        assert_eq!(graphml.matches("<edge ").count(), 2);
+        assert!(graphml.contains(r#"<edge source="n0" target="n1">"#));
+        assert!(graphml.contains(r#"<edge source="n1" target="n2">"#));
+        assert!(graphml.contains(r#"<data key="weight">0.75</data>"#));
+
+        let mut out = Vec::new();
+        similarity_matrix.write_graphml(&mut out, None).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains(r#"<node id="n2"/>"#));
+    }
+
+    #[test]
+    fn ranks_by_mode() {
+        // Hub 0 has three weak edges; 4 and 5 share a single strong one.
+        let similarity_matrix = SimilarityMatrix::from_triplets(
+            6,
+            0.0,
+            vec![(0, 1, 0.3), (0, 2, 0.35), (0, 3, 0.4), (4, 5, 0.9), (1, 2, 0.2)],
+        );
+
+        // 4 and 5 tie on both sibling count and similarity sum, so they rank by index.
+        assert_eq!(similarity_matrix.rank_by_weight(RankMode::ByWeightedDegree), vec![0, 2, 1, 4, 5, 3]);
+        assert_eq!(similarity_matrix.rank_by_weight(RankMode::ByMeanSimilarity), vec![4, 5, 3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn converts_to_dense() {
+        let similarity_matrix = SimilarityMatrix::from_triplets(3, 0.5, vec![(0, 1, 0.9), (1, 2, 0.4)]);
+
+        assert_eq!(
+            similarity_matrix.to_dense(),
+            vec![vec![1.0, 0.9, 0.0], vec![0.9, 1.0, 0.0], vec![0.0, 0.0, 1.0]],
+        );
+    }
+
+    #[test]
+    fn computes_row_weight() {
+        let row = Row::new(vec![
+            Score { sibling_index: 1, similarity: 0.5 },
+            Score { sibling_index: 4, similarity: 0.25 },
+        ]);
+
+        assert_eq!(row.weight(), (2, 0.75));
+        assert_eq!(Row::new(vec![]).weight(), (0, 0.0));
+    }
+
+    #[test]
+    fn streams_triplets() {
+        let names = string_vec(vec!["alejandro", "alexandro", "alejandra", "marlene", "marleny"]);
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+
+        let mut triplets = Vec::new();
+        let compared_pairs =
+            compute_triplets(
+                &names,
+                0.5,
+                &mut CartesianIndexPairIterator::new(names.len()),
+                metric,
+                |row, column, similarity| triplets.push((row, column, similarity)),
+            );
+
+        let similarity_matrix =
+            SimilarityMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric);
+        assert_eq!(compared_pairs, 10);
+        assert_eq!(triplets.len() * 2, similarity_matrix.rows.iter().map(|row| row.scores.len()).sum());
+        for (row, column, similarity) in triplets {
+            assert_eq!(similarity_matrix[row][column], similarity);
+        }
+    }
+
+    #[test]
+    fn cuts_rows_at_threshold() {
+        let row = Row::new(vec![
+            Score { sibling_index: 6, similarity: 0.7 },
+            Score { sibling_index: 1, similarity: 0.9 },
+            Score { sibling_index: 4, similarity: 0.7 },
+            Score { sibling_index: 3, similarity: 0.5 },
+        ]);
+
+        assert_eq!(row.cut_at(0.7), vec![1, 4, 6]);
+        assert_eq!(row.cut_at_sorted(0.7), vec![1, 4, 6]);
+        assert_eq!(row.cut_at(0.5), vec![1, 4, 6, 3]);
+        assert_eq!(row.cut_at_sorted(0.5), vec![1, 3, 4, 6]);
+        assert_eq!(row.cut_at(0.9), vec![1]);
+        assert!(row.cut_at(0.90001).is_empty());
+
+        assert_eq!(row.cut_at(-1.0), row.cut_at(0.0));
+        assert!(row.cut_at(2.0).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_nan_cut() {
+        Row::new(vec![]).cut_at(f64::NAN);
+    }
+
+    #[test]
+    fn ranks_equally_similar_siblings_by_index() {
+        let row = Row::new(vec![
+            Score { sibling_index: 7, similarity: 0.8 },
+            Score { sibling_index: 2, similarity: 0.9 },
+            Score { sibling_index: 5, similarity: 0.8 },
+            Score { sibling_index: 3, similarity: 0.8 },
+        ]);
+
+        assert_eq!(row.ranked_siblings(&HashSet::new()), vec![2, 3, 5, 7]);
+        assert_eq!(row.ranked_siblings(&[3].iter().copied().collect()), vec![2, 5, 7]);
+    }
+
+    #[test]
+    fn ranks_siblings_by_custom_comparator() {
+        let row = Row::new(vec![
+            Score { sibling_index: 7, similarity: 0.8 },
+            Score { sibling_index: 2, similarity: 0.9 },
+            Score { sibling_index: 5, similarity: 0.7 },
+        ]);
+        let degrees = [0, 0, 1, 0, 0, 4, 0, 2];
+
+        let by_degree = row.ranked_siblings_by(&HashSet::new(), |score_1, score_2|
+            degrees[score_2.sibling_index].cmp(&degrees[score_1.sibling_index]));
+
+        assert_eq!(by_degree, vec![5, 7, 2]);
+    }
+
+    #[test]
+    fn converts_compact_scores() {
+        let row = Row::new(vec![
+            Score { sibling_index: 3, similarity: 0.5 },
+            Score { sibling_index: 7, similarity: 0.1 },
+        ]);
+
+        let compact = row.to_compact().unwrap();
+        assert_eq!(compact[0], CompactScore { sibling_index: 3, similarity: 0.5 });
+        assert_eq!(compact[1].similarity, 0.1f32);
+
+        let widened = Row::from_compact(&compact);
+        assert_eq!(widened.scores[0].similarity, 0.5);
+        assert!((widened.scores[1].similarity - 0.1).abs() < 1e-7);
+        assert_ne!(widened.scores[1].similarity, 0.1);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn rejects_oversized_compact_index() {
+        let score = Score { sibling_index: u32::MAX as Index + 1, similarity: 0.5 };
+
+        assert!(CompactScore::try_from(&score).is_err());
+    }
+
     fn check_scores(similarity_matrix: &SimilarityMatrix, size: Size, scores: Vec<Scores>) {
         assert_eq!(similarity_matrix.size(), size);
 
@@ -0,0 +1,243 @@
+//! This module contains a lazily computed similarity matrix, which scores a row's candidate pairs
+//! only when the row is first accessed.
+//!
+//! Laziness pays off when only some rows are ever looked at, such as when querying the neighbors
+//! of a handful of elements. Clustering computes only the rows of cluster seeds and of the
+//! members of clusters being split, as seeds are ranked by their candidate count rather than by
+//! weight (which would compute every row up front). Clusters can therefore differ from those of
+//! the eager `SimilarityMatrix`. When most rows end up computed anyway, as in full threshold
+//! sweeps, the eager matrix is faster since it scores pairs in parallel.
+
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::cmp::Reverse;
+
+use crate::{Index, Size};
+use crate::graph::Graph;
+use crate::index_pair::IndexPairSource;
+use crate::sim_metric::Similarity;
+
+use super::{RankMode, Row, Score, SimilarityMatrix};
+
+/// A similarity matrix whose rows are computed and cached on first access.
+pub struct LazyMatrix<'a, T, M> {
+    /// The input set.
+    elements: &'a [T],
+    /// The minimum score to consider two elements similar.
+    min_similarity: Similarity,
+    /// The similarity metric.
+    similarity_metric: M,
+    /// Each element's candidate siblings, as yielded by the index pair iterator.
+    candidates: Vec<Vec<Index>>,
+    /// Each element's row, once computed. Rows are sorted by descending similarity.
+    rows: Vec<OnceCell<Row>>,
+}
+
+impl<'a, T, M> LazyMatrix<'a, T, M>
+    where M: Fn(&T, &T) -> Similarity
+{
+    /// Create a new instance of `LazyMatrix`. Index pairs are collected right away but no pair
+    /// is scored until one of its rows is accessed.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be clustered.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply for clustering.
+    ///
+    /// # Return
+    ///
+    /// The new matrix.
+    pub fn new<I: IndexPairSource>(
+        elements: &'a [T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> LazyMatrix<'a, T, M> {
+        assert!(!elements.is_empty(), "Cannot create matrix from empty vector");
+
+        let mut candidates = vec![Vec::new(); elements.len()];
+        for (row, column) in index_pair_iterator.filter(|(row, column)| row != column) {
+            candidates[row].push(column);
+            candidates[column].push(row);
+        }
+        for siblings in candidates.iter_mut() {
+            siblings.sort_unstable();
+            siblings.dedup();
+        }
+
+        let rows = (0..elements.len()).map(|_| OnceCell::new()).collect();
+
+        LazyMatrix { elements, min_similarity, similarity_metric, candidates, rows }
+    }
+
+    /// Return a row, computing it on first access. Pairs whose other row was already computed
+    /// reuse its score instead of calling the metric again.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the desired row.
+    ///
+    /// # Return
+    ///
+    /// The row, sorted by descending similarity and then by ascending sibling index.
+    pub fn row(&self, index: Index) -> &Row {
+        self.rows[index].get_or_init(|| {
+            let mut scores =
+                self.candidates[index].iter()
+                    .map(|&sibling_index| {
+                        let similarity = match self.rows[sibling_index].get() {
+                            Some(sibling_row) => sibling_row[index],
+                            None => (self.similarity_metric)(&self.elements[index], &self.elements[sibling_index]),
+                        };
+                        Score { sibling_index, similarity }
+                    })
+                    .filter(|score| score.similarity > 0.0 && score.similarity >= self.min_similarity)
+                    .collect::<Vec<Score>>();
+            scores.sort_by(|score_1, score_2|
+                score_2.similarity.partial_cmp(&score_1.similarity).unwrap()
+                    .then(score_1.sibling_index.cmp(&score_2.sibling_index)));
+            Row::new(scores)
+        })
+    }
+
+    /// Return the number of elements in this matrix.
+    pub fn size(&self) -> Size {
+        self.elements.len()
+    }
+
+    /// Return the number of rows computed so far.
+    pub fn computed_rows(&self) -> Size {
+        self.rows.iter().filter(|row| row.get().is_some()).count()
+    }
+
+    /// Compute all remaining rows and convert this matrix into an eager `SimilarityMatrix`.
+    pub fn to_matrix(&self) -> SimilarityMatrix {
+        let similarity_triplets =
+            (0..self.size())
+                .flat_map(|index| {
+                    self.row(index).scores.iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .map(move |score| (index, score.sibling_index, score.similarity))
+                })
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        SimilarityMatrix::from_triplets(self.size(), self.min_similarity, similarity_triplets)
+    }
+}
+
+/// `Graph` implementation computing rows as the clusterer visits them. Seeds are ranked by
+/// descending candidate count, then by ascending index, whatever the rank mode.
+impl<'a, T, M> Graph for LazyMatrix<'a, T, M>
+    where M: Fn(&T, &T) -> Similarity
+{
+    fn size(&self) -> Size {
+        LazyMatrix::size(self)
+    }
+
+    fn neighbors(&self, index: Index) -> Vec<(Index, Similarity)> {
+        self.row(index).scores.iter()
+            .map(|score| (score.sibling_index, score.similarity))
+            .collect::<Vec<(Index, Similarity)>>()
+    }
+
+    fn row(&self, index: Index) -> Cow<'_, Row> {
+        Cow::Borrowed(LazyMatrix::row(self, index))
+    }
+
+    fn rank_by_weight(&self, _mode: RankMode) -> Vec<Index> {
+        let mut indices = (0..self.size()).collect::<Vec<Index>>();
+        indices.sort_by_key(|index| Reverse(self.candidates[*index].len()));
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use strsim::normalized_damerau_levenshtein;
+
+    use crate::cluster::{Clusterer, ClustererConfig};
+    use crate::index_pair::cartesian::CartesianIndexPairIterator;
+    use crate::utils::string_vec;
+
+    use super::*;
+
+    fn names() -> Vec<String> {
+        string_vec(vec!["alejandro", "alexandro", "alejandra", "marlene", "marleny", "ricardo"])
+    }
+
+    #[test]
+    fn computes_rows_on_demand() {
+        let names = names();
+        let metric_calls = Cell::new(0);
+        let lazy_matrix = LazyMatrix::new(
+            &names,
+            0.5,
+            &mut CartesianIndexPairIterator::new(names.len()),
+            |s1: &String, s2: &String| {
+                metric_calls.set(metric_calls.get() + 1);
+                normalized_damerau_levenshtein(s1, s2)
+            },
+        );
+        assert_eq!(metric_calls.get(), 0);
+
+        let row = lazy_matrix.row(3);
+        assert_eq!(row.scores.len(), 1);
+        assert_eq!(row.scores[0].sibling_index, 4);
+        assert_eq!(metric_calls.get(), 5);
+        assert_eq!(lazy_matrix.computed_rows(), 1);
+
+        // Row 4 reuses its score against 3 and row 3 isn't recomputed.
+        lazy_matrix.row(4);
+        lazy_matrix.row(3);
+        assert_eq!(metric_calls.get(), 9);
+        assert_eq!(lazy_matrix.computed_rows(), 2);
+    }
+
+    #[test]
+    fn orders_equally_similar_siblings_by_index() {
+        let elements = vec![0, 1, 2, 3];
+        let lazy_matrix = LazyMatrix::new(
+            &elements,
+            0.0,
+            &mut CartesianIndexPairIterator::new(elements.len()),
+            |e1: &i32, e2: &i32| if e1.min(e2) == &0 { 0.5 } else { 0.9 },
+        );
+
+        let siblings = lazy_matrix.row(0).scores.iter().map(|score| score.sibling_index).collect::<Vec<Index>>();
+        assert_eq!(siblings, vec![1, 2, 3]);
+        let siblings = lazy_matrix.row(2).scores.iter().map(|score| score.sibling_index).collect::<Vec<Index>>();
+        assert_eq!(siblings, vec![1, 3, 0]);
+    }
+
+    #[test]
+    fn matches_eager_matrix() {
+        let names = names();
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+        let eager_matrix = SimilarityMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric);
+        let lazy_matrix = LazyMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric);
+
+        for i in 0..names.len() {
+            for j in 0..names.len() {
+                assert_eq!(lazy_matrix.row(i)[j], eager_matrix[i][j]);
+            }
+        }
+        assert_eq!(lazy_matrix.to_matrix().similarity_values, eager_matrix.similarity_values);
+    }
+
+    #[test]
+    fn clusters_computing_seed_rows_only() {
+        let names = names();
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+        let lazy_matrix = LazyMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric);
+
+        let config = ClustererConfig { split: false, ..ClustererConfig::default() };
+        let clusters = Clusterer::new(&config).run(&lazy_matrix);
+
+        assert_eq!(clusters, vec![vec![0, 1, 2], vec![3, 4], vec![5]]);
+        assert_eq!(lazy_matrix.computed_rows(), 3);
+    }
+}
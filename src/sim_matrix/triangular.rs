@@ -0,0 +1,217 @@
+//! This module contains an upper-triangular similarity matrix storing each edge only once, in the
+//! row of its lower index. It takes half the memory of `SimilarityMatrix` and suits read-only
+//! workloads such as exporting edges or looking up pair similarities.
+//!
+//! Clustering needs both directions of each edge, so this matrix cannot be clustered directly:
+//! convert it with `to_full` first.
+
+use std::ops::Index as BracketedIndex;
+
+use crate::{Index, Size};
+use crate::index_pair::IndexPairSource;
+use crate::sim_metric::Similarity;
+
+use super::{Row, Score, SimilarityMatrix, score_pairs};
+
+/// A similarity matrix holding each edge `(i, j)` only in row `min(i, j)`.
+#[derive(Debug)]
+pub struct UpperTriangularMatrix {
+    /// The collection of rows. Row `i` holds only the scores of siblings greater than `i`, sorted
+    /// by descending similarity and then by ascending sibling index.
+    rows: Vec<Row>,
+
+    /// The minimum similarity used for creating this matrix
+    min_similarity: Similarity,
+}
+
+impl UpperTriangularMatrix {
+    /// Create a new instance of `UpperTriangularMatrix`, measuring pairs as `SimilarityMatrix::new`
+    /// does.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - The input set vector containing elements to be compared.
+    /// * `min_similarity` - The minimum score to consider two elements similar.
+    /// * `index_pair_iterator` - The index pair iterator used to measure similarity  between to elements
+    /// * `similarity_metric` - The similarity metric to apply.
+    ///
+    /// # Return
+    ///
+    /// The new matrix.
+    pub fn new<T, I, M>(
+        elements: &[T],
+        min_similarity: Similarity,
+        index_pair_iterator: &mut I,
+        similarity_metric: M,
+    ) -> UpperTriangularMatrix
+        where
+            T: Sync + Send,
+            I: IndexPairSource,
+            M: Fn(&T, &T) -> Similarity + Sync,
+    {
+        assert!(!elements.is_empty(), "Cannot create matrix from empty vector");
+
+        let (similarity_triplets, _) =
+            score_pairs(
+                elements,
+                min_similarity,
+                index_pair_iterator,
+                |t1: &T, t2: &T| Some(similarity_metric(t1, t2)),
+            );
+
+        UpperTriangularMatrix::from_qualifying_triplets(elements.len(), min_similarity, similarity_triplets)
+    }
+
+    /// Place each triplet in the row of its lower index and sort rows by descending similarity.
+    fn from_qualifying_triplets(
+        size: Size,
+        min_similarity: Similarity,
+        similarity_triplets: Vec<(Index, Index, Similarity)>,
+    ) -> UpperTriangularMatrix {
+        let mut rows = vec![Row::new(vec![]); size];
+        for (row_index, column_index, similarity) in similarity_triplets {
+            if row_index != column_index {
+                rows[row_index.min(column_index)].scores.push(Score {
+                    sibling_index: row_index.max(column_index),
+                    similarity,
+                });
+            }
+        }
+
+        for row in rows.iter_mut() {
+            row.scores.sort_by(|score_1, score_2| {
+                score_2.similarity.partial_cmp(&score_1.similarity).unwrap()
+                    .then(score_1.sibling_index.cmp(&score_2.sibling_index))
+            });
+        }
+
+        UpperTriangularMatrix { rows, min_similarity }
+    }
+
+    /// Return the number of elements in this matrix.
+    pub fn size(&self) -> Size {
+        self.rows.len()
+    }
+
+    /// Return the minimum similarity used for creating this matrix.
+    pub fn min_similarity(&self) -> Similarity {
+        self.min_similarity
+    }
+
+    /// Return the stored similarity of two elements, in either order.
+    ///
+    /// # Arguments
+    ///
+    /// * `i` - The index of the first element.
+    /// * `j` - The index of the second element.
+    ///
+    /// # Return
+    ///
+    /// The pair's stored similarity or `0.0` if none is stored.
+    pub fn get(&self, i: Index, j: Index) -> Similarity {
+        self.rows[i.min(j)][i.max(j)]
+    }
+
+    /// Iterate over this matrix's edges, each yielded once as `(i, j, similarity)` with `i < j`.
+    pub fn edges(&self) -> impl Iterator<Item=(Index, Index, Similarity)> + '_ {
+        self.rows.iter().enumerate()
+            .flat_map(|(index, row)| {
+                row.scores.iter().map(move |score| (index, score.sibling_index, score.similarity))
+            })
+    }
+
+    /// Convert this matrix into a full, symmetric `SimilarityMatrix` suitable for clustering.
+    pub fn to_full(&self) -> SimilarityMatrix {
+        SimilarityMatrix::from_triplets(self.size(), self.min_similarity, self.edges().collect())
+    }
+}
+
+/// Conversion dropping the lower half of a full similarity matrix.
+impl From<&SimilarityMatrix> for UpperTriangularMatrix {
+    fn from(similarity_matrix: &SimilarityMatrix) -> UpperTriangularMatrix {
+        let similarity_triplets =
+            similarity_matrix.rows.iter().enumerate()
+                .flat_map(|(index, row)| {
+                    row.scores.iter()
+                        .filter(move |score| score.sibling_index > index)
+                        .map(move |score| (index, score.sibling_index, score.similarity))
+                })
+                .collect::<Vec<(Index, Index, Similarity)>>();
+
+        UpperTriangularMatrix::from_qualifying_triplets(
+            similarity_matrix.size(),
+            similarity_matrix.min_similarity(),
+            similarity_triplets,
+        )
+    }
+}
+
+/// Implementation of `std::ops::Index` for upper-triangular matrices, normalizing index order so
+/// that `matrix[(i, j)] == matrix[(j, i)]`.
+impl BracketedIndex<(Index, Index)> for UpperTriangularMatrix {
+    /// The data type of values returned by the indexing operator (`[]`).
+    type Output = Similarity;
+
+    /// Return the stored similarity of a pair, `0.0` if none is stored.
+    fn index(&self, (i, j): (Index, Index)) -> &Self::Output {
+        &self.rows[i.min(j)][i.max(j)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index_pair::cartesian::CartesianIndexPairIterator;
+    use crate::utils::string_vec;
+
+    use strsim::normalized_damerau_levenshtein;
+
+    use super::*;
+
+    fn names_matrices() -> (SimilarityMatrix, UpperTriangularMatrix) {
+        let names = string_vec(vec!["alejandro", "alexandro", "alejandra", "marlene", "marleny"]);
+        let metric = |s1: &String, s2: &String| normalized_damerau_levenshtein(s1, s2);
+
+        (
+            SimilarityMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric),
+            UpperTriangularMatrix::new(&names, 0.5, &mut CartesianIndexPairIterator::new(names.len()), metric),
+        )
+    }
+
+    #[test]
+    fn stores_each_edge_once() {
+        let (full, triangular) = names_matrices();
+
+        assert_eq!(triangular.size(), 5);
+        assert_eq!(triangular.edges().count() * 2, full.rows.iter().map(|row| row.scores.len()).sum());
+        assert!(triangular.edges().all(|(i, j, _)| i < j));
+        assert!(triangular.rows[4].scores.is_empty());
+    }
+
+    #[test]
+    fn looks_up_in_either_order() {
+        let (full, triangular) = names_matrices();
+
+        for i in 0..5 {
+            for j in 0..5 {
+                assert_eq!(triangular[(i, j)], full[i][j]);
+                assert_eq!(triangular.get(j, i), full[i][j]);
+            }
+        }
+    }
+
+    #[test]
+    fn converts_to_and_from_full_matrix() {
+        let (full, triangular) = names_matrices();
+
+        let converted = UpperTriangularMatrix::from(&full);
+        assert_eq!(converted.edges().collect::<Vec<_>>(), triangular.edges().collect::<Vec<_>>());
+
+        let restored = triangular.to_full();
+        assert_eq!(restored.min_similarity(), 0.5);
+        for i in 0..5 {
+            for j in 0..5 {
+                assert_eq!(restored[i][j], full[i][j]);
+            }
+        }
+    }
+}
@@ -1,8 +1,554 @@
 //! This module defines specifies how similarity between two items is established.
 
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use strsim::{hamming, jaro_winkler as strsim_jaro_winkler, normalized_damerau_levenshtein, normalized_levenshtein};
+
+use crate::Size;
+use crate::index_pair::ngrams;
+
 /// Similarity is a normalized value between `0.0` (no similarity at all) and `1.0` (actual
 /// identity). Similarity is the opposite of *distance*.
 pub type Similarity = f64;
 
 /// Measure the similarity between two values of a given type.
 pub type SimilarityMetric<T> = dyn Fn(&T, &T) -> Similarity;
+
+/// Return the normalized Levenshtein similarity metric for strings: `1.0` minus the edit
+/// distance divided by the length of the longer string.
+pub fn levenshtein_ratio() -> impl Fn(&str, &str) -> Similarity {
+    normalized_levenshtein
+}
+
+/// Return the normalized Damerau-Levenshtein similarity metric for strings. Unlike plain
+/// Levenshtein it counts transpositions as single edits, making it the recommended metric for
+/// names and other human-typed strings.
+///
+/// # Example
+///
+/// ```
+/// use grappolo::index_pair::cartesian::CartesianIndexPairIterator;
+/// use grappolo::sim_matrix::SimilarityMatrix;
+/// use grappolo::sim_metric::damerau_levenshtein_ratio;
+/// use grappolo::utils::string_vec;
+///
+/// let names = string_vec(vec!["marlene", "malrene", "ricardo"]);
+/// let metric = damerau_levenshtein_ratio();
+///
+/// let similarity_matrix = SimilarityMatrix::new(
+///     &names,
+///     0.8,
+///     &mut CartesianIndexPairIterator::new(names.len()),
+///     |s1: &String, s2: &String| metric(s1, s2),
+/// );
+///
+/// assert_eq!(similarity_matrix[0][1], 0.8571428571428572);
+/// assert_eq!(similarity_matrix[0][2], 0.0);
+/// ```
+pub fn damerau_levenshtein_ratio() -> impl Fn(&str, &str) -> Similarity {
+    normalized_damerau_levenshtein
+}
+
+/// Return the Jaro-Winkler similarity metric for strings, which favors strings sharing a common
+/// prefix and suits short strings.
+pub fn jaro_winkler() -> impl Fn(&str, &str) -> Similarity {
+    strsim_jaro_winkler
+}
+
+/// Return a similarity metric for numeric vectors, such as coordinate points, decaying
+/// exponentially with their Euclidean distance: `exp(-distance / scale)`. Identical vectors are
+/// fully similar and vectors `scale` apart are about `0.37` similar.
+///
+/// # Arguments
+///
+/// * `scale` - The distance over which similarity decays by a factor of `e`. Must be positive.
+///
+/// # Return
+///
+/// The metric, which panics when given vectors of different dimensions.
+///
+/// # Example
+///
+/// ```
+/// use grappolo::index_pair::cartesian::CartesianIndexPairIterator;
+/// use grappolo::sim_matrix::SimilarityMatrix;
+/// use grappolo::sim_metric::euclidean_similarity;
+///
+/// let points = vec![vec![0.0, 0.0], vec![3.0, 4.0], vec![100.0, 100.0]];
+/// let metric = euclidean_similarity(5.0);
+///
+/// let similarity_matrix = SimilarityMatrix::new(
+///     &points,
+///     0.1,
+///     &mut CartesianIndexPairIterator::new(points.len()),
+///     |p1: &Vec<f64>, p2: &Vec<f64>| metric(p1, p2),
+/// );
+///
+/// assert_eq!(similarity_matrix[0][1], (-1.0f64).exp());
+/// assert_eq!(similarity_matrix[0][2], 0.0);
+/// ```
+pub fn euclidean_similarity(scale: f64) -> impl Fn(&[f64], &[f64]) -> Similarity {
+    assert!(scale > 0.0, "Scale must be positive");
+
+    move |a: &[f64], b: &[f64]| {
+        assert_eq!(a.len(), b.len(), "Vectors must have the same dimension");
+
+        let distance =
+            a.iter().zip(b.iter())
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f64>()
+                .sqrt();
+        (-distance / scale).exp()
+    }
+}
+
+/// Compute the normalized Hamming similarity of two strings: the fraction of character positions
+/// holding the same character. Suits fixed-length codes such as phone numbers or hashes.
+///
+/// # Arguments
+///
+/// * `a` - The first string.
+/// * `b` - The second string.
+///
+/// # Return
+///
+/// The fraction of matching positions, `1.0` for two empty strings, or `0.0` if the strings'
+/// lengths differ, as Hamming distance is undefined for them.
+pub fn hamming_ratio(a: &str, b: &str) -> Similarity {
+    match hamming(a, b) {
+        Ok(_) if a.is_empty() => 1.0,
+        Ok(distance) => {
+            let length = a.chars().count();
+            (length - distance) as Similarity / length as Similarity
+        }
+        Err(_) => 0.0,
+    }
+}
+
+/// A string metric chosen at runtime.
+pub type DynStringMetric = Box<dyn Fn(&str, &str) -> Similarity + Sync>;
+
+/// Look up a built-in string metric by name, so metrics can be chosen at runtime (for instance,
+/// from a command line flag). Known names are `levenshtein`, `damerau_levenshtein`,
+/// `jaro_winkler`, `hamming` and `ngram_overlap` (which compares bigrams).
+///
+/// # Arguments
+///
+/// * `name` - The metric name.
+///
+/// # Return
+///
+/// The boxed metric or `None` if the name is unknown.
+///
+/// # Example
+///
+/// ```
+/// use grappolo::index_pair::cartesian::CartesianIndexPairIterator;
+/// use grappolo::sim_matrix::SimilarityMatrix;
+/// use grappolo::sim_metric::metric_by_name;
+/// use grappolo::utils::string_vec;
+///
+/// let names = string_vec(vec!["marlene", "malrene", "ricardo"]);
+/// let metric = metric_by_name("damerau_levenshtein").expect("Unknown metric");
+///
+/// let similarity_matrix = SimilarityMatrix::new_dyn(
+///     &names,
+///     0.8,
+///     &mut CartesianIndexPairIterator::new(names.len()),
+///     &|s1: &String, s2: &String| metric(s1, s2),
+/// );
+///
+/// assert_eq!(similarity_matrix[0][1], 0.8571428571428572);
+/// ```
+pub fn metric_by_name(name: &str) -> Option<DynStringMetric> {
+    match name {
+        "levenshtein" => Some(Box::new(levenshtein_ratio())),
+        "damerau_levenshtein" => Some(Box::new(damerau_levenshtein_ratio())),
+        "jaro_winkler" => Some(Box::new(jaro_winkler())),
+        "hamming" => Some(Box::new(hamming_ratio)),
+        "ngram_overlap" => Some(Box::new(|a: &str, b: &str| ngram_overlap(a, b, 2))),
+        _ => None,
+    }
+}
+
+/// Coefficient used to measure the similarity between two sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetCoefficient {
+    /// `|A∩B| / |A∪B|`
+    Jaccard,
+    /// `2|A∩B| / (|A| + |B|)`
+    Dice,
+    /// `|A∩B| / min(|A|, |B|)`
+    Overlap,
+}
+
+/// Compute the Jaccard coefficient `|A∩B| / |A∪B|` of two sets.
+///
+/// # Arguments
+///
+/// * `a_set` - The first set.
+/// * `b_set` - The second set.
+///
+/// # Return
+///
+/// The Jaccard coefficient, or `0.0` if both sets are empty.
+pub fn jaccard<K: Eq + Hash>(a_set: &HashSet<K>, b_set: &HashSet<K>) -> Similarity {
+    let intersection = intersection_count(a_set, b_set);
+    let union = a_set.len() + b_set.len() - intersection;
+
+    if union == 0 {
+        0.0
+    } else {
+        intersection as Similarity / union as Similarity
+    }
+}
+
+/// Compute the Dice coefficient `2|A∩B| / (|A| + |B|)` of two sets. Compared to Jaccard, Dice is
+/// gentler on differences in set size.
+///
+/// # Arguments
+///
+/// * `a_set` - The first set.
+/// * `b_set` - The second set.
+///
+/// # Return
+///
+/// The Dice coefficient, or `0.0` if both sets are empty.
+pub fn dice<K: Eq + Hash>(a_set: &HashSet<K>, b_set: &HashSet<K>) -> Similarity {
+    let total = a_set.len() + b_set.len();
+
+    if total == 0 {
+        0.0
+    } else {
+        2.0 * intersection_count(a_set, b_set) as Similarity / total as Similarity
+    }
+}
+
+/// Compute the overlap coefficient `|A∩B| / min(|A|, |B|)` of two sets. A set is fully similar
+/// to any of its supersets, which suits subset relationships.
+///
+/// # Arguments
+///
+/// * `a_set` - The first set.
+/// * `b_set` - The second set.
+///
+/// # Return
+///
+/// The overlap coefficient, or `0.0` if either set is empty.
+pub fn overlap<K: Eq + Hash>(a_set: &HashSet<K>, b_set: &HashSet<K>) -> Similarity {
+    let smallest = a_set.len().min(b_set.len());
+
+    if smallest == 0 {
+        0.0
+    } else {
+        intersection_count(a_set, b_set) as Similarity / smallest as Similarity
+    }
+}
+
+/// Measure the similarity of two strings as the overlap of their n-gram sets. Because these are
+/// the same n-grams `NGramPairs` blocks on, any candidate pair it yields gets a nonzero score.
+///
+/// # Arguments
+///
+/// * `a` - The first string.
+/// * `b` - The second string.
+/// * `ngram_length` - The length of the n-grams to compare.
+///
+/// # Return
+///
+/// The Jaccard coefficient of both strings' n-gram sets.
+pub fn ngram_overlap(a: &str, b: &str, ngram_length: Size) -> Similarity {
+    ngram_overlap_with(a, b, ngram_length, SetCoefficient::Jaccard)
+}
+
+/// Measure the similarity of two strings as the overlap of their n-gram sets using a given set
+/// coefficient.
+///
+/// # Arguments
+///
+/// * `a` - The first string.
+/// * `b` - The second string.
+/// * `ngram_length` - The length of the n-grams to compare.
+/// * `coefficient` - The set coefficient to apply.
+///
+/// # Return
+///
+/// The coefficient of both strings' n-gram sets, or `0.0` if either yields no n-gram.
+pub fn ngram_overlap_with(a: &str, b: &str, ngram_length: Size, coefficient: SetCoefficient) -> Similarity {
+    let a_set = ngrams(a, ngram_length).into_iter().collect::<HashSet<String>>();
+    let b_set = ngrams(b, ngram_length).into_iter().collect::<HashSet<String>>();
+
+    match coefficient {
+        SetCoefficient::Jaccard => jaccard(&a_set, &b_set),
+        SetCoefficient::Dice => dice(&a_set, &b_set),
+        SetCoefficient::Overlap => overlap(&a_set, &b_set),
+    }
+}
+
+/// A field metric composed with its field extractor, measuring whole records.
+type FieldMetric<R> = Box<dyn Fn(&R, &R) -> Similarity + Send + Sync>;
+
+/// A field's contribution to a record similarity.
+struct RecordField<R> {
+    /// The metric applied to the field, already composed with its extractor.
+    metric: FieldMetric<R>,
+    /// The field's weight in the record similarity.
+    weight: f64,
+    /// The similarity below which the whole record similarity drops to `0.0`, if any.
+    blocking_similarity: Option<Similarity>,
+}
+
+/// Builder of a record similarity metric combining per-field string metrics, as in the standard
+/// record-linkage scoring model. The record similarity is the weighted average of the field
+/// similarities, except that it drops to `0.0` as soon as a blocking field falls below its
+/// minimum similarity.
+///
+/// # Example
+///
+/// ```
+/// use grappolo::sim_metric::{RecordMetric, damerau_levenshtein_ratio, jaro_winkler};
+///
+/// struct Person { name: String, dob: String }
+///
+/// let metric = RecordMetric::new()
+///     .field(|person: &Person| person.name.as_str(), jaro_winkler(), 2.0)
+///     .blocking_field(|person: &Person| person.dob.as_str(), damerau_levenshtein_ratio(), 1.0, 0.9)
+///     .build();
+///
+/// let ana = Person { name: "ana".to_string(), dob: "1970-01-01".to_string() };
+/// let anna = Person { name: "anna".to_string(), dob: "1970-01-01".to_string() };
+/// let other_anna = Person { name: "anna".to_string(), dob: "1985-06-30".to_string() };
+///
+/// assert!(metric(&ana, &anna) > 0.9);
+/// assert_eq!(metric(&anna, &other_anna), 0.0);
+/// ```
+pub struct RecordMetric<R> {
+    fields: Vec<RecordField<R>>,
+}
+
+impl<R> RecordMetric<R> {
+    /// Create a record metric without fields.
+    pub fn new() -> RecordMetric<R> {
+        RecordMetric { fields: Vec::new() }
+    }
+
+    /// Add a field to the record similarity.
+    ///
+    /// # Arguments
+    ///
+    /// * `extractor` - The function extracting the field from a record.
+    /// * `metric` - The similarity metric to apply to the field.
+    /// * `weight` - The field's weight in the record similarity.
+    ///
+    /// # Return
+    ///
+    /// This builder.
+    pub fn field<F, M>(self, extractor: F, metric: M, weight: f64) -> RecordMetric<R>
+        where
+            F: Fn(&R) -> &str + Send + Sync + 'static,
+            M: Fn(&str, &str) -> Similarity + Send + Sync + 'static,
+    {
+        self.add_field(extractor, metric, weight, None)
+    }
+
+    /// Add a blocking field to the record similarity: records whose field similarity falls below
+    /// a minimum are not similar at all, regardless of their other fields.
+    ///
+    /// # Arguments
+    ///
+    /// * `extractor` - The function extracting the field from a record.
+    /// * `metric` - The similarity metric to apply to the field.
+    /// * `weight` - The field's weight in the record similarity.
+    /// * `min_similarity` - The minimum field similarity, `1.0` requiring an exact match.
+    ///
+    /// # Return
+    ///
+    /// This builder.
+    pub fn blocking_field<F, M>(self, extractor: F, metric: M, weight: f64, min_similarity: Similarity) -> RecordMetric<R>
+        where
+            F: Fn(&R) -> &str + Send + Sync + 'static,
+            M: Fn(&str, &str) -> Similarity + Send + Sync + 'static,
+    {
+        self.add_field(extractor, metric, weight, Some(min_similarity))
+    }
+
+    fn add_field<F, M>(mut self, extractor: F, metric: M, weight: f64, blocking_similarity: Option<Similarity>) -> RecordMetric<R>
+        where
+            F: Fn(&R) -> &str + Send + Sync + 'static,
+            M: Fn(&str, &str) -> Similarity + Send + Sync + 'static,
+    {
+        assert!(weight >= 0.0, "Field weight must not be negative");
+
+        self.fields.push(RecordField {
+            metric: Box::new(move |r1: &R, r2: &R| metric(extractor(r1), extractor(r2))),
+            weight,
+            blocking_similarity,
+        });
+        self
+    }
+
+    /// Measure the similarity of two records. Blocking fields are measured first so mismatches
+    /// skip the remaining fields.
+    ///
+    /// # Arguments
+    ///
+    /// * `r1` - The first record.
+    /// * `r2` - The second record.
+    ///
+    /// # Return
+    ///
+    /// The weighted average of the field similarities, or `0.0` if a blocking field mismatches or
+    /// all weights are zero.
+    pub fn similarity(&self, r1: &R, r2: &R) -> Similarity {
+        let mut weighted_sum = 0.0;
+        let mut weight_sum = 0.0;
+
+        let blocking_first =
+            self.fields.iter().filter(|field| field.blocking_similarity.is_some())
+                .chain(self.fields.iter().filter(|field| field.blocking_similarity.is_none()));
+        for field in blocking_first {
+            let similarity = (field.metric)(r1, r2);
+            if field.blocking_similarity.is_some_and(|min_similarity| similarity < min_similarity) {
+                return 0.0;
+            }
+            weighted_sum += field.weight * similarity;
+            weight_sum += field.weight;
+        }
+
+        if weight_sum == 0.0 {
+            0.0
+        } else {
+            weighted_sum / weight_sum
+        }
+    }
+
+    /// Turn this builder into a metric function suitable for `SimilarityMatrix::new`.
+    pub fn build(self) -> impl Fn(&R, &R) -> Similarity {
+        move |r1: &R, r2: &R| self.similarity(r1, r2)
+    }
+}
+
+impl<R> Default for RecordMetric<R> {
+    fn default() -> RecordMetric<R> {
+        RecordMetric::new()
+    }
+}
+
+/// Count the elements common to two sets.
+fn intersection_count<K: Eq + Hash>(a_set: &HashSet<K>, b_set: &HashSet<K>) -> Size {
+    let (smaller, larger) = if a_set.len() <= b_set.len() { (a_set, b_set) } else { (b_set, a_set) };
+    smaller.iter().filter(|element| larger.contains(element)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_string_metrics() {
+        assert_eq!(levenshtein_ratio()("marlene", "malrene"), 0.7142857142857143);
+        assert_eq!(damerau_levenshtein_ratio()("marlene", "malrene"), 0.8571428571428572);
+        assert_eq!(jaro_winkler()("martha", "martha"), 1.0);
+        assert!(jaro_winkler()("martha", "marhta") > 0.96);
+
+        assert_eq!(levenshtein_ratio()("abc", "xyz"), 0.0);
+        assert_eq!(damerau_levenshtein_ratio()("abc", "xyz"), 0.0);
+    }
+
+    #[test]
+    fn looks_up_metrics_by_name() {
+        for name in &["levenshtein", "damerau_levenshtein", "jaro_winkler", "hamming", "ngram_overlap"] {
+            assert_eq!(metric_by_name(name).unwrap()("marlene", "marlene"), 1.0);
+        }
+        assert_eq!(metric_by_name("levenshtein").unwrap()("marlene", "malrene"), 0.7142857142857143);
+        assert!(metric_by_name("soundex").is_none());
+    }
+
+    #[test]
+    fn computes_set_coefficients() {
+        let a_set = [1, 2, 3, 4].iter().copied().collect::<HashSet<i32>>();
+        let b_set = [3, 4, 5].iter().copied().collect::<HashSet<i32>>();
+        let c_set = [3, 4].iter().copied().collect::<HashSet<i32>>();
+        let empty_set = HashSet::new();
+
+        assert_eq!(jaccard(&a_set, &b_set), 2.0 / 5.0);
+        assert_eq!(dice(&a_set, &b_set), 4.0 / 7.0);
+        assert_eq!(overlap(&a_set, &b_set), 2.0 / 3.0);
+
+        assert_eq!(jaccard(&a_set, &c_set), 0.5);
+        assert_eq!(dice(&a_set, &c_set), 4.0 / 6.0);
+        assert_eq!(overlap(&a_set, &c_set), 1.0);
+
+        assert_eq!(jaccard(&a_set, &empty_set), 0.0);
+        assert_eq!(dice(&a_set, &empty_set), 0.0);
+        assert_eq!(overlap(&a_set, &empty_set), 0.0);
+    }
+
+    #[test]
+    fn computes_ngram_overlap() {
+        // "rustinomicon" has 11 distinct bigrams, "rustonomicon" has 10; they share 9.
+        assert_eq!(ngram_overlap("rustinomicon", "rustinomicon", 2), 1.0);
+        assert_eq!(ngram_overlap("rustinomicon", "rustonomicon", 2), 9.0 / 12.0);
+        assert_eq!(
+            ngram_overlap_with("rustinomicon", "rustonomicon", 2, SetCoefficient::Dice),
+            18.0 / 21.0);
+    }
+
+    #[test]
+    fn computes_zero_overlap_without_ngrams() {
+        assert_eq!(ngram_overlap("rustinomicon", "xyz", 2), 0.0);
+        assert_eq!(ngram_overlap("r", "r", 2), 0.0);
+        assert_eq!(ngram_overlap_with("r", "r", 2, SetCoefficient::Dice), 0.0);
+    }
+
+    #[test]
+    fn computes_euclidean_similarity() {
+        let metric = euclidean_similarity(2.0);
+
+        assert_eq!(metric(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]), 1.0);
+        assert_eq!(metric(&[0.0, 0.0], &[0.0, 2.0]), (-1.0f64).exp());
+        assert!(metric(&[0.0], &[1.0]) > metric(&[0.0], &[2.0]));
+    }
+
+    struct Person {
+        name: String,
+        city: String,
+    }
+
+    fn person(name: &str, city: &str) -> Person {
+        Person { name: name.to_string(), city: city.to_string() }
+    }
+
+    #[test]
+    fn combines_record_fields() {
+        let metric = RecordMetric::new()
+            .field(|person: &Person| person.name.as_str(), levenshtein_ratio(), 3.0)
+            .field(|person: &Person| person.city.as_str(), levenshtein_ratio(), 1.0);
+
+        assert_eq!(metric.similarity(&person("abcd", "lima"), &person("abcd", "lima")), 1.0);
+        // Names are 0.75 similar and cities 0.0: (3.0 * 0.75 + 1.0 * 0.0) / 4.0
+        assert_eq!(metric.similarity(&person("abcd", "lima"), &person("abce", "oslo")), 0.5625);
+        assert_eq!(RecordMetric::<Person>::new().similarity(&person("a", "b"), &person("a", "b")), 0.0);
+    }
+
+    #[test]
+    fn short_circuits_on_blocking_fields() {
+        let metric = RecordMetric::new()
+            .field(|person: &Person| person.name.as_str(), levenshtein_ratio(), 1.0)
+            .blocking_field(|person: &Person| person.city.as_str(), levenshtein_ratio(), 1.0, 0.75)
+            .build();
+
+        assert_eq!(metric(&person("abcd", "lima"), &person("abcd", "pisa")), 0.0);
+        assert_eq!(metric(&person("abcd", "lima"), &person("abce", "limo")), 0.75);
+    }
+
+    #[test]
+    fn computes_hamming_ratio() {
+        assert_eq!(hamming_ratio("555-1234", "555-1234"), 1.0);
+        assert_eq!(hamming_ratio("555-1234", "555-1243"), 0.75);
+        assert_eq!(hamming_ratio("ñandú", "ñandu"), 0.8);
+        assert_eq!(hamming_ratio("", ""), 1.0);
+        assert_eq!(hamming_ratio("555-1234", "555-123"), 0.0);
+    }
+}
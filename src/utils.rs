@@ -1,16 +1,19 @@
 //! This module provides miscellaneous utility functions.
 
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader};
 use std::time::SystemTime;
 
+/// The magic bytes opening every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
 pub fn string_vec(strs: Vec<&str>) -> Vec<String> {
     strs.iter().map(|s| String::from(*s)).collect::<Vec<String>>()
 }
 
 
 pub fn read_all_file_lines(filename: String) -> Vec<String> {
-    read_file_lines(filename, usize::max_value())
+    read_file_lines(filename, usize::MAX)
 }
 
 pub fn read_file_lines(filename: String, up_to: usize) -> Vec<String> {
@@ -23,11 +26,61 @@ pub fn read_file_lines(filename: String, up_to: usize) -> Vec<String> {
         .collect::<Vec<String>>()
 }
 
+/// Lazily read the lines of a file, for preprocessing passes over files too large to hold in
+/// memory.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the file to read.
+///
+/// # Return
+///
+/// An iterator over the file's lines or the I/O error preventing its opening.
+pub fn stream_file_lines(filename: String) -> io::Result<impl Iterator<Item=io::Result<String>>> {
+    Ok(BufReader::new(File::open(filename)?).lines())
+}
+
+/// Read all lines from a file that may be gzip-compressed. Compressed files are recognized by a
+/// `.gz` extension or by their magic bytes and require the `flate2` feature.
+///
+/// # Arguments
+///
+/// * `filename` - The name of the (possibly compressed) file to read.
+///
+/// # Return
+///
+/// The file's lines or the I/O error preventing their reading.
+pub fn read_lines_maybe_gzip(filename: String) -> io::Result<Vec<String>> {
+    let mut reader = BufReader::new(File::open(&filename)?);
+
+    let is_gzip = filename.ends_with(".gz") || reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        read_gzip_lines(reader)
+    } else {
+        reader.lines().collect()
+    }
+}
+
+#[cfg(feature = "flate2")]
+fn read_gzip_lines(reader: BufReader<File>) -> io::Result<Vec<String>> {
+    BufReader::new(flate2::bufread::MultiGzDecoder::new(reader))
+        .lines()
+        .collect()
+}
+
+#[cfg(not(feature = "flate2"))]
+fn read_gzip_lines(_reader: BufReader<File>) -> io::Result<Vec<String>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Reading gzip files requires the `flate2` feature",
+    ))
+}
+
 pub fn open_output_file(filename: String) -> File {
     OpenOptions::new()
         .create(true)
         .write(true)
-        .append(false)
+        .truncate(true)
         .open(filename)
         .expect("Error opening output file")
 }
@@ -38,3 +91,75 @@ pub fn millis_since(start_time: SystemTime) -> u128 {
         .expect("Error in time!")
         .as_millis()
 }
+
+/// Build a temporary file path unique to this process and call, so that concurrently running
+/// tests (and test binaries) never share scratch files.
+///
+/// # Arguments
+///
+/// * `name` - The name ending the path, including any extension.
+///
+/// # Return
+///
+/// A path in the system's temporary directory.
+#[cfg(test)]
+pub(crate) fn unique_temp_path(name: &str) -> std::path::PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("grappolo-{}-{}-{}", std::process::id(), count, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn reads_plain_file_lines() {
+        let lines = read_lines_maybe_gzip(String::from("data/surnames.txt")).unwrap();
+
+        assert_eq!(lines, read_all_file_lines(String::from("data/surnames.txt")));
+    }
+
+    #[test]
+    fn streams_file_lines() {
+        let lines =
+            stream_file_lines(String::from("data/surnames.txt")).unwrap()
+                .collect::<io::Result<Vec<String>>>()
+                .unwrap();
+
+        assert_eq!(lines, read_all_file_lines(String::from("data/surnames.txt")));
+        assert!(stream_file_lines(String::from("data/no-such-file.txt")).is_err());
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn reads_gzip_file_lines() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        let filename = unique_temp_path("gzip-lines.txt.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"alejandro\nalejo\n").unwrap();
+        std::fs::write(&filename, encoder.finish().unwrap()).unwrap();
+
+        let lines = read_lines_maybe_gzip(filename.to_string_lossy().into_owned()).unwrap();
+
+        assert_eq!(lines, string_vec(vec!["alejandro", "alejo"]));
+    }
+
+    #[cfg(not(feature = "flate2"))]
+    #[test]
+    fn rejects_gzip_file_without_feature() {
+        let filename = unique_temp_path("gzip-lines.bin");
+        let mut file = open_output_file(filename.to_string_lossy().into_owned());
+        file.write_all(&GZIP_MAGIC).unwrap();
+
+        let result = read_lines_maybe_gzip(filename.to_string_lossy().into_owned());
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+}